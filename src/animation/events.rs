@@ -0,0 +1,75 @@
+use super::sprite::PlaybackEvent;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use std::borrow::Cow;
+
+/// Sent when an animation starts playing, including after being restarted
+pub struct AnimationStarted<T> {
+    pub entity: Entity,
+    pub animation: T,
+}
+
+/// Sent when a one-shot animation reaches the end of its last frame
+pub struct AnimationFinished<T> {
+    pub entity: Entity,
+    pub animation: T,
+}
+
+/// Sent every time a repeating animation loops back, or a ping-pong animation completes a cycle
+pub struct AnimationLooped<T> {
+    pub entity: Entity,
+    pub animation: T,
+}
+
+/// Sent when an animation is replaced by another one before it finished
+pub struct AnimationInterrupted<T> {
+    pub entity: Entity,
+    pub animation: T,
+}
+
+/// Sent when an animation enters a frame tagged with a named event
+pub struct AnimationFrameEvent<T> {
+    pub entity: Entity,
+    pub animation: T,
+    pub name: Cow<'static, str>,
+}
+
+/// Writers for every animation event of the animation enum `T`
+#[derive(SystemParam)]
+pub struct AnimationEventWriters<'w, T: Send + Sync + 'static> {
+    started: EventWriter<'w, AnimationStarted<T>>,
+    finished: EventWriter<'w, AnimationFinished<T>>,
+    looped: EventWriter<'w, AnimationLooped<T>>,
+    interrupted: EventWriter<'w, AnimationInterrupted<T>>,
+    frame: EventWriter<'w, AnimationFrameEvent<T>>,
+}
+
+impl<'w, T: Send + Sync + 'static> AnimationEventWriters<'w, T> {
+    /// Send the playback events recorded by the sprite sheet of `entity`, in order
+    pub fn send(&mut self, entity: Entity, events: impl IntoIterator<Item = PlaybackEvent<T>>) {
+        for event in events {
+            match event {
+                PlaybackEvent::Started(animation) => {
+                    self.started.send(AnimationStarted { entity, animation });
+                }
+                PlaybackEvent::Finished(animation) => {
+                    self.finished.send(AnimationFinished { entity, animation });
+                }
+                PlaybackEvent::Looped(animation) => {
+                    self.looped.send(AnimationLooped { entity, animation });
+                }
+                PlaybackEvent::Interrupted(animation) => {
+                    self.interrupted
+                        .send(AnimationInterrupted { entity, animation });
+                }
+                PlaybackEvent::Frame(animation, name) => {
+                    self.frame.send(AnimationFrameEvent {
+                        entity,
+                        animation,
+                        name,
+                    });
+                }
+            }
+        }
+    }
+}
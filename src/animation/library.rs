@@ -0,0 +1,509 @@
+use super::sprite::{AnimationLookup, AnimationSpriteSheet};
+use super::{Animation, FrameRate};
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use ron::value::{Number, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Registers the `AnimationLibrary` asset and its loader
+pub struct AnimationLibraryPlugin;
+
+impl Plugin for AnimationLibraryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<AnimationLibrary>()
+            .init_asset_loader::<AnimationLibraryLoader>();
+    }
+}
+
+/// A set of named animation clips, loaded from an `.anim.ron` or `.anim.json` file.
+///
+/// Each clip is keyed by name and defines its atlas `indices`, either an `fps` or a total
+/// `duration` in seconds, and an optional `mode` (`once`, `repeat`, `repeat_from` or
+/// `ping_pong`). `repeat_from` loops back to the frame given by the `repeat_from` field.
+/// `events` tags frames (by their position in the clip) with named events.
+///
+/// ```ron
+/// {
+///     "Idle": (indices: "0..=5", fps: 12),
+///     "Run": (indices: "20..=25", fps: 12, events: [(1, "footstep"), (4, "footstep")]),
+///     "Attack1": (indices: [90, 91, 92, 93, 94, 95], duration: 0.5, mode: "once"),
+/// }
+/// ```
+#[derive(Debug, Clone, Default, TypeUuid)]
+#[uuid = "5d3a6b5e-3f0c-4a8e-9d0b-7c1e2f4a9b61"]
+pub struct AnimationLibrary {
+    clips: Arc<HashMap<String, Animation>>,
+}
+
+impl AnimationLibrary {
+    /// Parse a library from RON
+    pub fn from_ron(bytes: &[u8]) -> Result<Self, AnimationLibraryError> {
+        let clips: HashMap<String, Value> = ron::de::from_bytes(bytes)
+            .map_err(|error| AnimationLibraryError::Syntax(error.to_string()))?;
+        Self::from_values(clips)
+    }
+
+    /// Parse a library from JSON
+    pub fn from_json(bytes: &[u8]) -> Result<Self, AnimationLibraryError> {
+        let clips: HashMap<String, Value> = serde_json::from_slice(bytes)
+            .map_err(|error| AnimationLibraryError::Syntax(error.to_string()))?;
+        Self::from_values(clips)
+    }
+
+    fn from_values(values: HashMap<String, Value>) -> Result<Self, AnimationLibraryError> {
+        let mut clips = HashMap::with_capacity(values.len());
+        for (name, value) in values {
+            let animation = parse_clip(&name, value)?;
+            clips.insert(name, animation);
+        }
+
+        Ok(Self {
+            clips: Arc::new(clips),
+        })
+    }
+
+    /// Returns the clip with the given name
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&Animation> {
+        self.clips.get(name)
+    }
+
+    /// Looks up the clip for an animation enum, by its clip name
+    #[must_use]
+    pub fn get_animation<T: AnimationLookup<T>>(&self, anim_enum: &T) -> Option<Animation> {
+        self.get(T::clip_name(anim_enum)).cloned()
+    }
+}
+
+/// Error produced when an animation library file can't be turned into clips
+#[derive(Debug)]
+pub enum AnimationLibraryError {
+    /// The file isn't valid RON/JSON, or isn't a map of clip names to clips
+    Syntax(String),
+    /// A clip isn't a struct of fields
+    InvalidClip { clip: String, reason: String },
+    /// A clip field is missing, unknown or has an invalid value
+    InvalidField {
+        clip: String,
+        field: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for AnimationLibraryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Syntax(error) => write!(f, "invalid animation library: {error}"),
+            Self::InvalidClip { clip, reason } => write!(f, "animation clip `{clip}`: {reason}"),
+            Self::InvalidField {
+                clip,
+                field,
+                reason,
+            } => write!(f, "animation clip `{clip}`, field `{field}`: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for AnimationLibraryError {}
+
+#[derive(Default)]
+pub struct AnimationLibraryLoader;
+
+impl AssetLoader for AnimationLibraryLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let is_json = load_context
+                .path()
+                .extension()
+                .is_some_and(|extension| extension == "json");
+            let library = if is_json {
+                AnimationLibrary::from_json(bytes)?
+            } else {
+                AnimationLibrary::from_ron(bytes)?
+            };
+            load_context.set_default_asset(LoadedAsset::new(library));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["anim.ron", "anim.json"]
+    }
+}
+
+/// Gives sprite sheets the clips of the library they reference once it has loaded, and
+/// re-resolves their current clip whenever that library is hot-reloaded
+pub fn apply_animation_library_system<T>(
+    mut events: EventReader<AssetEvent<AnimationLibrary>>,
+    libraries: Res<Assets<AnimationLibrary>>,
+    mut query: Query<(&Handle<AnimationLibrary>, &mut AnimationSpriteSheet<T>)>,
+) where
+    T: Default + Eq + Copy + Hash + AnimationLookup<T> + Send + Sync + 'static,
+{
+    let modified: Vec<&Handle<AnimationLibrary>> = events
+        .iter()
+        .filter_map(|event| match event {
+            AssetEvent::Modified { handle } => Some(handle),
+            _ => None,
+        })
+        .collect();
+
+    for (handle, mut sprite_sheet) in query.iter_mut() {
+        let reloaded = modified.contains(&handle);
+        if sprite_sheet.library.is_some() && !reloaded {
+            continue;
+        }
+        if let Some(library) = libraries.get(handle) {
+            sprite_sheet.library = Some(library.clone());
+            if reloaded {
+                sprite_sheet.refresh_current_animation();
+            }
+        }
+    }
+}
+
+fn parse_clip(clip: &str, value: Value) -> Result<Animation, AnimationLibraryError> {
+    let field_error = |field: &str, reason: String| AnimationLibraryError::InvalidField {
+        clip: clip.to_string(),
+        field: field.to_string(),
+        reason,
+    };
+
+    let Value::Map(fields) = value else {
+        return Err(AnimationLibraryError::InvalidClip {
+            clip: clip.to_string(),
+            reason: "expected a struct of clip fields".to_string(),
+        });
+    };
+
+    let mut indices = None;
+    let mut fps = None;
+    let mut duration = None;
+    let mut mode = None;
+    let mut repeat_from = None;
+    let mut events = Vec::new();
+    for (key, value) in fields.iter() {
+        let Value::String(field) = key else {
+            return Err(AnimationLibraryError::InvalidClip {
+                clip: clip.to_string(),
+                reason: format!("field names must be identifiers or strings, found {key:?}"),
+            });
+        };
+        match field.as_str() {
+            "indices" => indices = Some(parse_indices(value).map_err(|r| field_error(field, r))?),
+            "fps" => fps = Some(parse_positive(value).map_err(|r| field_error(field, r))?),
+            "duration" => {
+                duration = Some(parse_positive(value).map_err(|r| field_error(field, r))?)
+            }
+            "mode" => match value {
+                Value::String(name) => mode = Some(name.clone()),
+                _ => return Err(field_error(field, "expected a string".to_string())),
+            },
+            "repeat_from" => {
+                repeat_from = Some(parse_index(value).map_err(|r| field_error(field, r))?)
+            }
+            "events" => events = parse_events(value).map_err(|r| field_error(field, r))?,
+            _ => {
+                return Err(field_error(
+                    field,
+                    "unknown field, expected one of `indices`, `fps`, `duration`, `mode`, \
+                     `repeat_from`, `events`"
+                        .to_string(),
+                ))
+            }
+        }
+    }
+
+    let Some(indices) = indices else {
+        return Err(field_error("indices", "is required".to_string()));
+    };
+    if indices.is_empty() {
+        return Err(field_error(
+            "indices",
+            "must contain at least one frame".to_string(),
+        ));
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    let frame_count_f64 = indices.len() as f64;
+    let (field, frame_rate, frame_seconds) = match (fps, duration) {
+        (Some(fps), None) => {
+            let frame_duration = Duration::try_from_secs_f64(1.0 / fps)
+                .map_err(|_| field_error("fps", format!("{fps} is too low")))?;
+            (
+                "fps",
+                FrameRate::from_frame_duration(frame_duration),
+                1.0 / fps,
+            )
+        }
+        (None, Some(duration)) => {
+            let total_duration = Duration::try_from_secs_f64(duration)
+                .map_err(|_| field_error("duration", format!("{duration} is too long")))?;
+            (
+                "duration",
+                FrameRate::from_total_duration(total_duration),
+                duration / frame_count_f64,
+            )
+        }
+        (Some(_), Some(_)) => {
+            return Err(field_error(
+                "duration",
+                "cannot be combined with `fps`".to_string(),
+            ))
+        }
+        (None, None) => {
+            return Err(field_error(
+                "fps",
+                "is required, unless `duration` is given".to_string(),
+            ))
+        }
+    };
+    if !matches!(Duration::try_from_secs_f64(frame_seconds), Ok(frame) if !frame.is_zero()) {
+        return Err(field_error(
+            field,
+            "frames would last less than a nanosecond".to_string(),
+        ));
+    }
+
+    let frame_count = indices.len();
+    let animation = Animation::from_indices(indices, frame_rate);
+    let mut animation = match mode.as_deref() {
+        None | Some("repeat") | Some("repeat_from") => {
+            let loop_from = repeat_from.unwrap_or(0);
+            if loop_from >= frame_count {
+                return Err(field_error(
+                    "repeat_from",
+                    format!("frame {loop_from} is out of range, the clip has {frame_count} frames"),
+                ));
+            }
+            animation.repeat_from(loop_from)
+        }
+        Some(other) if repeat_from.is_some() => {
+            return Err(field_error(
+                "repeat_from",
+                format!("only applies to mode `repeat_from`, not `{other}`"),
+            ))
+        }
+        Some("once") => animation.once(),
+        Some("ping_pong") => animation.ping_pong(),
+        Some(other) => {
+            return Err(field_error(
+                "mode",
+                format!(
+                    "unknown mode `{other}`, expected one of `once`, `repeat`, `repeat_from`, \
+                     `ping_pong`"
+                ),
+            ))
+        }
+    };
+
+    for (frame_index, name) in events {
+        if frame_index >= frame_count {
+            return Err(field_error(
+                "events",
+                format!("frame {frame_index} is out of range, the clip has {frame_count} frames"),
+            ));
+        }
+        animation = animation.with_event(frame_index, name);
+    }
+
+    Ok(animation)
+}
+
+/// Accepts either a list of atlas indices or a range string like `"20..=25"` or `"20..26"`
+fn parse_indices(value: &Value) -> Result<Vec<usize>, String> {
+    match value {
+        Value::Seq(values) => values.iter().map(parse_index).collect(),
+        Value::String(range) => {
+            let parse_bound = |bound: &str| {
+                bound
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|_| format!("invalid range bound `{bound}` in `{range}`"))
+            };
+            if let Some((start, end)) = range.split_once("..=") {
+                let (start, end) = (parse_bound(start)?, parse_bound(end)?);
+                Ok((start..=end).collect())
+            } else if let Some((start, end)) = range.split_once("..") {
+                let (start, end) = (parse_bound(start)?, parse_bound(end)?);
+                Ok((start..end).collect())
+            } else {
+                Err(format!(
+                    "expected a range like \"20..=25\", found `{range}`"
+                ))
+            }
+        }
+        _ => Err("expected a list of atlas indices or a range like \"20..=25\"".to_string()),
+    }
+}
+
+/// Accepts a list of `(frame, "name")` pairs
+fn parse_events(value: &Value) -> Result<Vec<(usize, String)>, String> {
+    let Value::Seq(values) = value else {
+        return Err("expected a list of (frame, \"name\") pairs".to_string());
+    };
+    values
+        .iter()
+        .map(|pair| match pair {
+            Value::Seq(pair) if pair.len() == 2 => match &pair[1] {
+                Value::String(name) => Ok((parse_index(&pair[0])?, name.clone())),
+                other => Err(format!("expected an event name, found {other:?}")),
+            },
+            _ => Err(format!("expected a (frame, \"name\") pair, found {pair:?}")),
+        })
+        .collect()
+}
+
+fn parse_index(value: &Value) -> Result<usize, String> {
+    match value {
+        Value::Number(Number::Integer(index)) => {
+            usize::try_from(*index).map_err(|_| format!("index {index} can't be negative"))
+        }
+        _ => Err(format!("expected a non-negative integer, found {value:?}")),
+    }
+}
+
+fn parse_positive(value: &Value) -> Result<f64, String> {
+    let number = match value {
+        Value::Number(number) => number.into_f64(),
+        _ => return Err(format!("expected a number, found {value:?}")),
+    };
+    if number.is_finite() && number > 0.0 {
+        Ok(number)
+    } else {
+        Err(format!("must be a positive number, found {number}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::Mode;
+
+    fn parse(ron: &str) -> Result<Animation, AnimationLibraryError> {
+        let library = AnimationLibrary::from_ron(ron.as_bytes())?;
+        Ok(library.get("Clip").cloned().expect("the clip is missing"))
+    }
+
+    fn invalid_field(ron: &str) -> String {
+        match parse(ron) {
+            Err(AnimationLibraryError::InvalidField { field, .. }) => field,
+            other => panic!("expected an invalid field, got {other:?}"),
+        }
+    }
+
+    fn indices(animation: &Animation) -> Vec<usize> {
+        animation.frames.iter().map(|frame| frame.index).collect()
+    }
+
+    #[test]
+    fn ranges_and_lists_give_the_indices() {
+        let inclusive = parse(r#"{ "Clip": (indices: "20..=23", fps: 10) }"#).unwrap();
+        assert_eq!(indices(&inclusive), [20, 21, 22, 23]);
+        assert_eq!(inclusive.frames[0].duration, Duration::from_millis(100));
+        assert_eq!(inclusive.mode, Mode::RepeatFrom(0));
+
+        let exclusive = parse(r#"{ "Clip": (indices: "20..23", fps: 10, mode: "once") }"#).unwrap();
+        assert_eq!(indices(&exclusive), [20, 21, 22]);
+        assert_eq!(exclusive.mode, Mode::Once);
+
+        let listed = parse(r#"{ "Clip": (indices: [4, 2, 7], duration: 0.6) }"#).unwrap();
+        assert_eq!(indices(&listed), [4, 2, 7]);
+        assert_eq!(listed.frames[1].duration, Duration::from_millis(200));
+
+        assert_eq!(
+            invalid_field(r#"{ "Clip": (indices: "5..", fps: 10) }"#),
+            "indices"
+        );
+        assert_eq!(
+            invalid_field(r#"{ "Clip": (indices: "5..5", fps: 10) }"#),
+            "indices"
+        );
+        assert_eq!(
+            invalid_field(r#"{ "Clip": (indices: [1, -2], fps: 10) }"#),
+            "indices"
+        );
+    }
+
+    #[test]
+    fn fps_and_duration_are_exclusive() {
+        assert_eq!(
+            invalid_field(r#"{ "Clip": (indices: "0..=3", fps: 10, duration: 1.0) }"#),
+            "duration"
+        );
+        assert_eq!(invalid_field(r#"{ "Clip": (indices: "0..=3") }"#), "fps");
+    }
+
+    #[test]
+    fn out_of_range_timings_are_rejected() {
+        assert_eq!(
+            invalid_field(r#"{ "Clip": (indices: "0..=3", fps: 0) }"#),
+            "fps"
+        );
+        assert_eq!(
+            invalid_field(r#"{ "Clip": (indices: "0..=3", fps: 1e-300) }"#),
+            "fps"
+        );
+        assert_eq!(
+            invalid_field(r#"{ "Clip": (indices: "0..=3", fps: 1e300) }"#),
+            "fps"
+        );
+        assert_eq!(
+            invalid_field(r#"{ "Clip": (indices: "0..=3", duration: 1e300) }"#),
+            "duration"
+        );
+        assert_eq!(
+            invalid_field(r#"{ "Clip": (indices: "0..=3", duration: -1.0) }"#),
+            "duration"
+        );
+    }
+
+    #[test]
+    fn bad_fields_are_reported() {
+        assert_eq!(
+            invalid_field(r#"{ "Clip": (indices: "0..=3", fps: 10, speed: 2) }"#),
+            "speed"
+        );
+        assert_eq!(
+            invalid_field(r#"{ "Clip": (indices: "0..=3", fps: 10, mode: "sometimes") }"#),
+            "mode"
+        );
+        assert_eq!(
+            invalid_field(r#"{ "Clip": (indices: "0..=3", fps: 10, repeat_from: 4) }"#),
+            "repeat_from"
+        );
+        assert_eq!(
+            invalid_field(r#"{ "Clip": (indices: "0..=3", fps: 10, events: [(4, "step")]) }"#),
+            "events"
+        );
+        assert!(matches!(
+            parse(r#"{ "Clip": 3 }"#),
+            Err(AnimationLibraryError::InvalidClip { .. })
+        ));
+        assert!(matches!(
+            parse("not ron"),
+            Err(AnimationLibraryError::Syntax(_))
+        ));
+    }
+
+    #[test]
+    fn json_libraries_parse_like_ron() {
+        let library = AnimationLibrary::from_json(
+            br#"{ "Clip": { "indices": "0..=1", "fps": 5, "events": [[1, "step"]] } }"#,
+        )
+        .unwrap();
+        let clip = library.get("Clip").unwrap();
+        assert_eq!(indices(clip), [0, 1]);
+        assert_eq!(clip.frames[1].events, ["step"]);
+    }
+}
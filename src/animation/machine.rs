@@ -0,0 +1,466 @@
+use super::sprite::{AnimationLookup, AnimationSpriteSheet};
+use bevy::prelude::*;
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Debug, Write};
+use std::hash::Hash;
+use std::sync::Arc;
+
+/// Values the transition conditions of an `AnimationStateMachine` are tested against
+#[derive(Debug, Default, Clone)]
+pub struct AnimationParameters {
+    floats: HashMap<Cow<'static, str>, f32>,
+    bools: HashMap<Cow<'static, str>, bool>,
+    triggers: HashSet<Cow<'static, str>>,
+}
+
+impl AnimationParameters {
+    pub fn set_float(&mut self, name: impl Into<Cow<'static, str>>, value: f32) {
+        self.floats.insert(name.into(), value);
+    }
+
+    pub fn set_bool(&mut self, name: impl Into<Cow<'static, str>>, value: bool) {
+        self.bools.insert(name.into(), value);
+    }
+
+    /// Set a trigger, which stays set until a transition consumes it. Triggers set while a
+    /// locked animation plays are kept until it can be left, so input is buffered.
+    pub fn set_trigger(&mut self, name: impl Into<Cow<'static, str>>) {
+        self.triggers.insert(name.into());
+    }
+
+    /// Returns the float parameter, or 0 if it was never set
+    #[must_use]
+    pub fn float(&self, name: &str) -> f32 {
+        self.floats.get(name).copied().unwrap_or_default()
+    }
+
+    /// Returns the bool parameter, or false if it was never set
+    #[must_use]
+    pub fn bool(&self, name: &str) -> bool {
+        self.bools.get(name).copied().unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn is_triggered(&self, name: &str) -> bool {
+        self.triggers.contains(name)
+    }
+}
+
+/// A condition on the parameters that must hold for a transition to be taken
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    /// The float parameter is strictly greater than the value
+    Greater(Cow<'static, str>, f32),
+    /// The float parameter is strictly less than the value
+    Less(Cow<'static, str>, f32),
+    /// The bool parameter equals the value
+    Bool(Cow<'static, str>, bool),
+    /// The trigger is set. It is consumed when the transition is taken
+    Trigger(Cow<'static, str>),
+}
+
+impl Condition {
+    #[must_use]
+    pub fn holds(&self, parameters: &AnimationParameters) -> bool {
+        match self {
+            Self::Greater(name, value) => parameters.float(name) > *value,
+            Self::Less(name, value) => parameters.float(name) < *value,
+            Self::Bool(name, value) => parameters.bool(name) == *value,
+            Self::Trigger(name) => parameters.is_triggered(name),
+        }
+    }
+}
+
+/// A transition between two animations of an `AnimationStateMachine`
+#[derive(Debug, Clone)]
+pub struct Transition<T> {
+    /// Animation the transition leaves, or `None` to leave any animation
+    pub from: Option<T>,
+    pub to: T,
+    /// Conditions that must all hold
+    pub conditions: Vec<Condition>,
+    /// How far into the current animation (from 0 to 1) the transition may be taken. Setting one
+    /// lets the transition leave a locked one-shot animation once it is far enough along.
+    pub exit_time: Option<f32>,
+    /// Transitions with a higher priority are tested first
+    pub priority: i32,
+    /// Whether the transition may cut a locked one-shot animation at any time
+    pub interrupts: bool,
+}
+
+impl<T: Copy> Transition<T> {
+    pub fn new(from: T, to: T) -> Self {
+        Self {
+            from: Some(from),
+            to,
+            conditions: Vec::new(),
+            exit_time: None,
+            priority: 0,
+            interrupts: false,
+        }
+    }
+
+    /// A transition that can be taken from any animation
+    pub fn from_any(to: T) -> Self {
+        Self {
+            from: None,
+            ..Self::new(to, to)
+        }
+    }
+
+    #[must_use]
+    pub fn when(mut self, condition: Condition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+
+    #[must_use]
+    pub fn exit_time(mut self, exit_time: f32) -> Self {
+        self.exit_time = Some(exit_time);
+        self
+    }
+
+    #[must_use]
+    pub fn priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    #[must_use]
+    pub fn interrupting(mut self) -> Self {
+        self.interrupts = true;
+        self
+    }
+}
+
+/// Picks the animation of the entity's `AnimationSpriteSheet<T>`, following the first available
+/// transition out of the current animation
+#[derive(Component)]
+pub struct AnimationStateMachine<T> {
+    /// Sorted by descending priority, keeping the definition order otherwise
+    transitions: Arc<Vec<Transition<T>>>,
+    pub parameters: AnimationParameters,
+}
+
+impl<T: Copy + Eq> AnimationStateMachine<T> {
+    pub fn new(transitions: impl IntoIterator<Item = Transition<T>>) -> Self {
+        let mut transitions: Vec<_> = transitions.into_iter().collect();
+        transitions.sort_by_key(|transition| Reverse(transition.priority));
+        Self {
+            transitions: Arc::new(transitions),
+            parameters: AnimationParameters::default(),
+        }
+    }
+
+    /// Whether `transition` can be taken right now
+    ///
+    /// `progress` is the normalized time of the current animation, and `locked` whether it is a
+    /// one-shot animation still playing.
+    fn is_available(
+        &self,
+        transition: &Transition<T>,
+        current: T,
+        progress: f32,
+        locked: bool,
+    ) -> bool {
+        let leaves_current = transition.from.is_none_or(|from| from == current);
+        // Re-entering the current animation is only meaningful once it has finished
+        let restarts_current = transition.to == current && progress < 1.0;
+        let exit_time_reached = transition
+            .exit_time
+            .map_or(!locked, |exit_time| progress >= exit_time);
+
+        leaves_current
+            && !restarts_current
+            && (exit_time_reached || transition.interrupts)
+            && transition
+                .conditions
+                .iter()
+                .all(|condition| condition.holds(&self.parameters))
+    }
+
+    /// Returns the first available transition out of `current`
+    fn evaluate(&self, current: T, progress: f32, locked: bool) -> Option<&Transition<T>> {
+        self.transitions
+            .iter()
+            .find(|transition| self.is_available(transition, current, progress, locked))
+    }
+
+    /// Describes the current state, the parameters, and which transitions out of the current
+    /// animation are available
+    pub fn dump(&self, sprite_sheet: &AnimationSpriteSheet<T>) -> String
+    where
+        T: Default + Hash + Debug + AnimationLookup<T>,
+    {
+        let current = logical_animation(sprite_sheet);
+        let progress = sprite_sheet
+            .state
+            .normalized_time(&sprite_sheet.get_current_animation());
+        let locked = sprite_sheet.locked && !sprite_sheet.state.is_ended();
+
+        let mut dump = format!(
+            "state: {current:?} ({:.0}%{}{})\n",
+            progress * 100.0,
+            if locked { ", locked" } else { "" },
+            if current != sprite_sheet.current_animation {
+                format!(", via {:?}", sprite_sheet.current_animation)
+            } else {
+                String::new()
+            }
+        );
+        let _ = writeln!(
+            dump,
+            "parameters: floats {:?}, bools {:?}, triggers {:?}",
+            self.parameters.floats, self.parameters.bools, self.parameters.triggers
+        );
+        for transition in self.transitions.iter() {
+            if transition.from.is_some_and(|from| from != current) {
+                continue;
+            }
+            let available = self.is_available(transition, current, progress, locked);
+            let _ = writeln!(
+                dump,
+                "  [{}] {} -> {:?} (priority {}{}{}) when {:?}",
+                if available { "x" } else { " " },
+                transition
+                    .from
+                    .map_or("Any".to_string(), |from| format!("{from:?}")),
+                transition.to,
+                transition.priority,
+                transition
+                    .exit_time
+                    .map_or(String::new(), |exit_time| format!(
+                        ", exit at {:.0}%",
+                        exit_time * 100.0
+                    )),
+                if transition.interrupts {
+                    ", interrupts"
+                } else {
+                    ""
+                },
+                transition.conditions,
+            );
+        }
+        dump
+    }
+}
+
+/// The state the machine is in: while a via animation plays, that's the animation it leads to
+fn logical_animation<T: Copy>(sprite_sheet: &AnimationSpriteSheet<T>) -> T {
+    sprite_sheet
+        .queued_animation
+        .unwrap_or(sprite_sheet.current_animation)
+}
+
+/// Takes the first available transition of every state machine, after the animations advanced
+pub fn animation_state_machine_system<T>(
+    mut query: Query<(&mut AnimationStateMachine<T>, &mut AnimationSpriteSheet<T>)>,
+) where
+    T: Default + Eq + Copy + Hash + AnimationLookup<T> + Send + Sync + 'static,
+{
+    for (mut machine, mut sprite_sheet) in query.iter_mut() {
+        let current = logical_animation(&sprite_sheet);
+        let progress = sprite_sheet
+            .state
+            .normalized_time(&sprite_sheet.get_current_animation());
+        let locked = sprite_sheet.locked && !sprite_sheet.state.is_ended();
+
+        let Some(transition) = machine.evaluate(current, progress, locked) else {
+            // Nothing can use the triggers now, so don't let them fire later on
+            if !locked {
+                machine.parameters.triggers.clear();
+            }
+            continue;
+        };
+
+        let to = transition.to;
+        // Taking the transition consumes its triggers, the others were meant for the animation
+        // being left
+        machine.parameters.triggers.clear();
+        sprite_sheet.force_animation(to);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::{Animation, FrameRate};
+    use std::time::Duration;
+
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+    enum Clip {
+        #[default]
+        Idle,
+        Run,
+        Attack,
+        Combo,
+    }
+
+    impl AnimationLookup<Clip> for Clip {
+        fn get_animation(anim_enum: &Clip) -> Animation {
+            let frame_rate = FrameRate::from_frame_duration(Duration::from_millis(100));
+            match anim_enum {
+                Clip::Idle | Clip::Run => Animation::from_indices(0..4, frame_rate).repeat(),
+                Clip::Attack | Clip::Combo => Animation::from_indices(4..8, frame_rate).once(),
+            }
+        }
+
+        fn clip_name(anim_enum: &Clip) -> &'static str {
+            match anim_enum {
+                Clip::Idle => "Idle",
+                Clip::Run => "Run",
+                Clip::Attack => "Attack",
+                Clip::Combo => "Combo",
+            }
+        }
+    }
+
+    fn taken(
+        machine: &AnimationStateMachine<Clip>,
+        current: Clip,
+        progress: f32,
+        locked: bool,
+    ) -> Option<Clip> {
+        machine
+            .evaluate(current, progress, locked)
+            .map(|transition| transition.to)
+    }
+
+    /// An app running the state machine of an entity playing `Attack`, which is locked
+    fn attacking(transitions: Vec<Transition<Clip>>) -> (App, Entity) {
+        let mut app = App::new();
+        app.add_system(animation_state_machine_system::<Clip>);
+        let mut sprite_sheet = AnimationSpriteSheet::<Clip>::default();
+        sprite_sheet.set_animation(Clip::Attack);
+        assert!(sprite_sheet.locked);
+        let entity = app
+            .world
+            .spawn((AnimationStateMachine::new(transitions), sprite_sheet))
+            .id();
+        (app, entity)
+    }
+
+    fn current(app: &App, entity: Entity) -> Clip {
+        app.world
+            .get::<AnimationSpriteSheet<Clip>>(entity)
+            .unwrap()
+            .current_animation
+    }
+
+    #[test]
+    fn higher_priorities_are_tested_first() {
+        let mut machine = AnimationStateMachine::new([
+            Transition::from_any(Clip::Run).when(Condition::Greater("speed".into(), 0.0)),
+            Transition::from_any(Clip::Combo).when(Condition::Greater("speed".into(), 0.0)),
+            Transition::from_any(Clip::Attack)
+                .when(Condition::Trigger("attack".into()))
+                .priority(1),
+        ]);
+        assert_eq!(taken(&machine, Clip::Idle, 0.0, false), None);
+
+        // Both speed transitions hold, the first one defined wins
+        machine.parameters.set_float("speed", 1.0);
+        assert_eq!(taken(&machine, Clip::Idle, 0.0, false), Some(Clip::Run));
+
+        machine.parameters.set_trigger("attack");
+        assert_eq!(taken(&machine, Clip::Idle, 0.0, false), Some(Clip::Attack));
+    }
+
+    #[test]
+    fn locked_clips_are_left_at_their_exit_time_or_by_interrupting() {
+        let machine = AnimationStateMachine::new([
+            Transition::new(Clip::Attack, Clip::Combo).exit_time(0.5),
+            Transition::new(Clip::Attack, Clip::Idle),
+        ]);
+        assert_eq!(taken(&machine, Clip::Attack, 0.25, true), None);
+        assert_eq!(taken(&machine, Clip::Attack, 0.5, true), Some(Clip::Combo));
+        // Unlocked, transitions without exit time are available right away
+        assert_eq!(taken(&machine, Clip::Attack, 0.25, false), Some(Clip::Idle));
+
+        let machine = AnimationStateMachine::new([
+            Transition::from_any(Clip::Run).interrupting(),
+            Transition::from_any(Clip::Idle),
+        ]);
+        assert_eq!(taken(&machine, Clip::Attack, 0.0, true), Some(Clip::Run));
+        // Only leaving the animation it's listed from
+        let machine = AnimationStateMachine::new([Transition::new(Clip::Combo, Clip::Run)]);
+        assert_eq!(taken(&machine, Clip::Attack, 1.0, false), None);
+    }
+
+    #[test]
+    fn restarting_waits_for_the_end() {
+        let mut machine = AnimationStateMachine::new([
+            Transition::from_any(Clip::Attack).when(Condition::Trigger("attack".into()))
+        ]);
+        machine.parameters.set_trigger("attack");
+        assert_eq!(taken(&machine, Clip::Attack, 0.5, false), None);
+        assert_eq!(
+            taken(&machine, Clip::Attack, 1.0, false),
+            Some(Clip::Attack)
+        );
+        assert_eq!(taken(&machine, Clip::Idle, 0.5, false), Some(Clip::Attack));
+    }
+
+    #[test]
+    fn triggers_are_buffered_while_locked() {
+        let (mut app, entity) = attacking(vec![Transition::new(Clip::Attack, Clip::Combo)
+            .when(Condition::Trigger("attack".into()))
+            .exit_time(0.7)]);
+        let triggered = |app: &App| {
+            app.world
+                .get::<AnimationStateMachine<Clip>>(entity)
+                .unwrap()
+                .parameters
+                .is_triggered("attack")
+        };
+
+        app.world
+            .get_mut::<AnimationStateMachine<Clip>>(entity)
+            .unwrap()
+            .parameters
+            .set_trigger("attack");
+        app.update();
+        assert_eq!(current(&app, entity), Clip::Attack);
+        assert!(triggered(&app));
+
+        app.world
+            .get_mut::<AnimationSpriteSheet<Clip>>(entity)
+            .unwrap()
+            .update_state(Duration::from_millis(300));
+        app.update();
+        assert_eq!(current(&app, entity), Clip::Combo);
+        assert!(!triggered(&app));
+
+        // Unused while unlocked, a trigger doesn't wait for a later chance
+        app.world
+            .get_mut::<AnimationSpriteSheet<Clip>>(entity)
+            .unwrap()
+            .force_animation(Clip::Idle);
+        app.world
+            .get_mut::<AnimationStateMachine<Clip>>(entity)
+            .unwrap()
+            .parameters
+            .set_trigger("attack");
+        app.update();
+        assert!(!triggered(&app));
+    }
+
+    #[test]
+    fn forcing_bypasses_locks() {
+        let (mut app, entity) = attacking(Vec::new());
+        let mut sprite_sheet = app
+            .world
+            .get_mut::<AnimationSpriteSheet<Clip>>(entity)
+            .unwrap();
+        sprite_sheet.set_animation(Clip::Run);
+        assert_eq!(sprite_sheet.current_animation, Clip::Attack);
+
+        sprite_sheet.force_animation(Clip::Run);
+        assert_eq!(sprite_sheet.current_animation, Clip::Run);
+        assert!(!sprite_sheet.locked);
+        app.update();
+        assert_eq!(current(&app, entity), Clip::Run);
+    }
+}
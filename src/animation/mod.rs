@@ -1,8 +1,68 @@
+pub mod events;
+pub mod library;
+pub mod machine;
 pub mod sprite;
 pub mod state;
 
+use crate::time_scale::TimeScalePlugin;
+use bevy::prelude::*;
 use core::time::Duration;
+use events::{
+    AnimationFinished, AnimationFrameEvent, AnimationInterrupted, AnimationLooped, AnimationStarted,
+};
+use library::{apply_animation_library_system, AnimationLibraryPlugin};
+use machine::animation_state_machine_system;
+use sprite::{animate_sprite_sheets_system, AnimationLookup};
+use std::borrow::Cow;
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+/// Drives every `AnimationSpriteSheet<T>` entity: advances its animation, writes the sprite
+/// index, promotes queued animations, runs its `AnimationStateMachine<T>` if it has one, and
+/// sends the animation events for `T`
+pub struct AnimationPlugin<T>(PhantomData<T>);
+
+impl<T> Default for AnimationPlugin<T> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T> Plugin for AnimationPlugin<T>
+where
+    T: Default + Eq + Copy + Hash + AnimationLookup<T> + Send + Sync + 'static,
+{
+    fn build(&self, app: &mut App) {
+        // Shared by the animation plugins of every animation enum
+        if !app.is_plugin_added::<AnimationLibraryPlugin>() {
+            app.add_plugin(AnimationLibraryPlugin);
+        }
+        if !app.is_plugin_added::<TimeScalePlugin>() {
+            app.add_plugin(TimeScalePlugin);
+        }
 
+        app.add_event::<AnimationStarted<T>>()
+            .add_event::<AnimationFinished<T>>()
+            .add_event::<AnimationLooped<T>>()
+            .add_event::<AnimationInterrupted<T>>()
+            .add_event::<AnimationFrameEvent<T>>()
+            .add_systems(
+                (
+                    apply_animation_library_system::<T>,
+                    animate_sprite_sheets_system::<T>,
+                    animation_state_machine_system::<T>,
+                )
+                    .chain()
+                    .in_set(AnimationSet),
+            );
+    }
+}
+
+/// The systems advancing sprite sheet animations, for every animation enum
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AnimationSet;
+
+#[derive(Debug, Clone)]
 pub struct Animation {
     /// Frames
     pub frames: Vec<Frame>,
@@ -11,12 +71,14 @@ pub struct Animation {
 }
 
 /// A single animation frame
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Frame {
     /// Index in the sprite atlas
     pub index: usize,
     /// How long should the frame be displayed
     pub duration: Duration,
+    /// Named events sent when the frame is entered (e.g. "hitbox_on", "footstep")
+    pub events: Vec<Cow<'static, str>>,
 }
 
 impl Animation {
@@ -75,6 +137,22 @@ impl Animation {
         self
     }
 
+    /// Tag a frame with a named event, sent whenever the animation enters that frame
+    ///
+    /// # Panics
+    ///
+    /// Panics if `frame_index` is not a frame of the animation
+    #[must_use]
+    pub fn with_event(mut self, frame_index: usize, name: impl Into<Cow<'static, str>>) -> Self {
+        assert!(
+            frame_index < self.frames.len(),
+            "frame {frame_index} is out of range for an animation of {} frames",
+            self.frames.len()
+        );
+        self.frames[frame_index].events.push(name.into());
+        self
+    }
+
     pub(crate) fn has_frames(&self) -> bool {
         !self.frames.is_empty()
     }
@@ -121,7 +199,11 @@ impl Frame {
             !duration.is_zero(),
             "zero-duration is invalid for animation frame"
         );
-        Self { index, duration }
+        Self {
+            index,
+            duration,
+            events: Vec::new(),
+        }
     }
 }
 
@@ -1,5 +1,10 @@
 use crate::animation;
+use crate::animation::events::AnimationEventWriters;
+use crate::animation::library::AnimationLibrary;
+use crate::time_scale::{ScaledTime, TimeScaleOverride};
 use bevy::prelude::*;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::hash::Hash;
 use std::time::Duration;
 
@@ -11,40 +16,168 @@ pub struct AnimationSpriteSheet<T> {
     pub locked: bool,
     pub column_count: usize,
     pub state: animation::state::State,
+    /// Clips loaded from an `AnimationLibrary`, taking precedence over the built-in ones
+    pub library: Option<AnimationLibrary>,
+    /// Animations played when going from one animation to another, keyed by `(from, to)`.
+    /// They must be one-shot animations, `to` starts once they finish.
+    pub via_animations: HashMap<(T, T), T>,
+    /// Playback changes not yet sent as events
+    pub(crate) pending_events: Vec<PlaybackEvent<T>>,
+}
+
+/// A playback change of an `AnimationSpriteSheet`, recorded until it is sent as an event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlaybackEvent<T> {
+    Started(T),
+    Finished(T),
+    Looped(T),
+    Interrupted(T),
+    /// A frame tagged with the named event was entered
+    Frame(T, Cow<'static, str>),
 }
 
 pub trait AnimationLookup<T> {
+    /// Built-in definition of the animation, used when no library defines it
     fn get_animation(anim_enum: &T) -> animation::Animation;
+    /// Name of the animation's clip in an `AnimationLibrary`
+    fn clip_name(anim_enum: &T) -> &'static str;
 }
 
 /// AnimationSpriteSheet should always have T as an enum of all the animations
 impl<T: Default + Eq + Copy + Hash + AnimationLookup<T>> AnimationSpriteSheet<T> {
     pub fn set_animation(&mut self, animation: T) {
-        if self.current_animation == animation || self.locked {
+        // A finished animation can be restarted by setting it again
+        if (self.current_animation == animation && !self.state.is_ended()) || self.locked {
+            return;
+        }
+        // Already playing the via animation leading to it
+        if self.queued_animation == Some(animation) {
             return;
         }
 
-        if self.get_animation(animation).mode == animation::Mode::Once {
-            self.locked = true;
-        } else {
-            self.locked = false;
+        if self.state.is_started() && !self.state.is_ended() {
+            self.pending_events
+                .push(PlaybackEvent::Interrupted(self.current_animation));
         }
 
-        self.current_animation = animation;
+        let via = self
+            .via_animations
+            .get(&(self.current_animation, animation))
+            .copied();
+        self.queued_animation = via.map(|_| animation);
+
+        // Via animations don't lock, so the transition can still be cut short
+        self.locked = via.is_none() && self.get_animation(animation).mode == animation::Mode::Once;
+
+        self.current_animation = via.unwrap_or(animation);
         self.state.reset();
     }
 
+    /// Set the animation even if the current one is locked, dropping any queued animation
+    pub fn force_animation(&mut self, animation: T) {
+        if self.current_animation == animation && !self.state.is_ended() {
+            return;
+        }
+
+        self.locked = false;
+        self.queued_animation = None;
+        self.set_animation(animation);
+    }
+
     /// Looks up the current animation based on the enum provided
     pub fn get_current_animation(&self) -> animation::Animation {
-        T::get_animation(&self.current_animation)
+        self.get_animation(self.current_animation)
     }
 
     pub fn get_animation(&self, animation: T) -> animation::Animation {
-        T::get_animation(&animation)
+        self.library
+            .as_ref()
+            .and_then(|library| library.get_animation(&animation))
+            .unwrap_or_else(|| T::get_animation(&animation))
+    }
+
+    /// Re-resolve the current animation and clamp the state into its frame range
+    ///
+    /// Needed whenever the clip definitions change, e.g. when the library is hot-reloaded.
+    pub fn refresh_current_animation(&mut self) {
+        let animation = self.get_current_animation();
+        if animation.mode != animation::Mode::Once {
+            self.locked = false;
+        }
+        self.state.clamp(&animation);
     }
 
-    /// Update the animation state
+    /// Returns whether the current animation is playing a frame tagged with the named event
+    ///
+    /// Unlike the frame events, which are sent once when the frame is entered, this holds for as
+    /// long as the frame is shown, e.g. for the active frames of an attack.
+    pub fn is_in_tagged_frame(&self, name: &str) -> bool {
+        if !self.state.is_started() || self.state.is_ended() {
+            return false;
+        }
+        self.get_current_animation()
+            .frames
+            .get(self.state.animation_frame_index())
+            .is_some_and(|frame| frame.events.iter().any(|event| event == name))
+    }
+
+    /// Update the animation state, recording the playback changes along the way
     pub fn update_state(&mut self, duration: Duration) {
-        self.state.update(&self.get_current_animation(), duration);
+        let animation = self.get_current_animation();
+        let current = self.current_animation;
+        let was_ended = self.state.is_ended();
+        let loop_count = self.state.loop_count();
+
+        if !self.state.is_started() {
+            self.pending_events.push(PlaybackEvent::Started(current));
+        }
+        let pending_events = &mut self.pending_events;
+        self.state
+            .update_with_events(&animation, duration, |event| {
+                pending_events.push(PlaybackEvent::Frame(current, event.clone()));
+            });
+
+        for _ in loop_count..self.state.loop_count() {
+            self.pending_events.push(PlaybackEvent::Looped(current));
+        }
+        if self.state.is_ended() && !was_ended {
+            self.pending_events.push(PlaybackEvent::Finished(current));
+        }
+    }
+
+    /// Take the playback changes recorded since the last call, in order
+    pub fn drain_events(&mut self) -> Vec<PlaybackEvent<T>> {
+        std::mem::take(&mut self.pending_events)
+    }
+}
+
+/// Advances every sprite sheet animation, unlocking finished animations and starting the queued
+/// one, then sends the recorded playback changes as events
+pub fn animate_sprite_sheets_system<T>(
+    time: ScaledTime,
+    mut animation_events: AnimationEventWriters<T>,
+    mut query: Query<(
+        Entity,
+        &mut AnimationSpriteSheet<T>,
+        &mut TextureAtlasSprite,
+        Option<&TimeScaleOverride>,
+    )>,
+) where
+    T: Default + Eq + Copy + Hash + AnimationLookup<T> + Send + Sync + 'static,
+{
+    for (entity, mut sprite_sheet, mut sprite, time_scale_override) in query.iter_mut() {
+        sprite_sheet.update_state(time.delta(time_scale_override));
+        sprite.index = sprite_sheet.state.frame_index();
+
+        let has_next = sprite_sheet.locked || sprite_sheet.queued_animation.is_some();
+        if has_next && sprite_sheet.state.is_ended() {
+            sprite_sheet.locked = false;
+
+            if let Some(queued_animation) = sprite_sheet.queued_animation.take() {
+                sprite_sheet.set_animation(queued_animation);
+            }
+        }
+
+        animation_events.send(entity, sprite_sheet.drain_events());
     }
 }
@@ -1,15 +1,47 @@
+use std::borrow::Cow;
 use std::time::Duration;
 use super::{Animation, Frame, Mode};
 
+/// Fastest playback speed, faster ones are clamped to it
+pub const MAX_SPEED: f32 = 100.0;
+
 /// Animation state
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Clone)]
 pub struct State {
     animation_frame_index: usize,
     sprite_frame_index: usize,
     elapsed_in_frame: Duration,
     /// Control ping_pong backward frame navigation.
     going_backward: bool,
+    /// Whether the first frame has been entered (and its events sent)
+    is_started: bool,
     is_ended: bool,
+    /// Number of times a repeating animation looped back, or a ping-pong one completed a cycle
+    loop_count: usize,
+    /// Whether the position was set by a seek before the animation started
+    is_seeked: bool,
+    /// Playback speed multiplier
+    speed: f32,
+    is_paused: bool,
+    is_reversed: bool,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            animation_frame_index: 0,
+            sprite_frame_index: 0,
+            elapsed_in_frame: Duration::ZERO,
+            going_backward: false,
+            is_started: false,
+            is_ended: false,
+            loop_count: 0,
+            is_seeked: false,
+            speed: 1.0,
+            is_paused: false,
+            is_reversed: false,
+        }
+    }
 }
 
 impl State {
@@ -22,8 +54,130 @@ impl State {
     /// Reset animation state
     ///
     /// The animation will restart from the first frame, like if the animation was freshly spawned.
+    /// The playback speed, pause and direction are kept.
     pub fn reset(&mut self) {
-        *self = Self::default();
+        *self = Self {
+            speed: self.speed,
+            is_paused: self.is_paused,
+            is_reversed: self.is_reversed,
+            ..Self::default()
+        };
+    }
+
+    /// Returns the playback speed multiplier
+    #[must_use]
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Set the playback speed multiplier, e.g. 0.5 for slow-motion or 2 for haste
+    ///
+    /// Speeds above `MAX_SPEED` are clamped to it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `speed` is negative or not finite, use `set_reversed` to play backward.
+    pub fn set_speed(&mut self, speed: f32) {
+        assert!(
+            speed.is_finite() && speed >= 0.0,
+            "invalid playback speed {speed}"
+        );
+        self.speed = speed.min(MAX_SPEED);
+    }
+
+    /// Returns true if the animation is paused
+    #[must_use]
+    pub fn is_paused(&self) -> bool {
+        self.is_paused
+    }
+
+    /// Pause the animation, it stays on its current frame until resumed
+    pub fn pause(&mut self) {
+        self.is_paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.is_paused = false;
+    }
+
+    /// Returns true if the animation plays backward
+    #[must_use]
+    pub fn is_reversed(&self) -> bool {
+        self.is_reversed
+    }
+
+    /// Play the animation backward, or forward again
+    ///
+    /// A reversed animation starts from its last frame. A one-shot animation ends on its first
+    /// frame, a repeating one loops its repeated frames backward, and a ping-pong one bounces
+    /// starting from its last frame.
+    pub fn set_reversed(&mut self, reversed: bool) {
+        self.is_reversed = reversed;
+    }
+
+    /// Jump to the start of the frame at `frame_index` in the animation
+    ///
+    /// The events of the frame are not sent. A finished one-shot animation plays again from there.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `frame_index` is out of range
+    pub fn seek_to_frame(&mut self, animation: &Animation, frame_index: usize) {
+        assert!(
+            frame_index < animation.frames.len(),
+            "frame index {frame_index} out of range for an animation of {} frames",
+            animation.frames.len()
+        );
+        self.seek(animation, frame_index, Duration::ZERO);
+    }
+
+    /// Jump to how far into its frames the animation is, from 0 to 1 like `normalized_time`
+    ///
+    /// The events of the frame are not sent. Seeking a one-shot animation to 1 finishes it, and a
+    /// finished one plays again from anywhere before that. NaN seeks to the start.
+    pub fn seek_to_normalized_time(&mut self, animation: &Animation, normalized_time: f32) {
+        debug_assert!(animation.has_frames());
+        let normalized_time = if normalized_time.is_nan() {
+            0.0
+        } else {
+            normalized_time.clamp(0.0, 1.0)
+        };
+        let total: Duration = animation.frames.iter().map(|frame| frame.duration).sum();
+        let mut remaining = total.mul_f32(normalized_time);
+
+        let frame_count = animation.frames.len();
+        let is_reversed = self.is_reversed;
+        let in_playback_order = |position: usize| {
+            if is_reversed {
+                frame_count - 1 - position
+            } else {
+                position
+            }
+        };
+        for frame_index in (0..frame_count).map(in_playback_order) {
+            let duration = animation.frames[frame_index].duration;
+            if remaining < duration {
+                self.seek(animation, frame_index, remaining);
+                return;
+            }
+            remaining -= duration;
+        }
+
+        let end_frame_index = in_playback_order(frame_count - 1);
+        self.seek(
+            animation,
+            end_frame_index,
+            animation.frames[end_frame_index].duration,
+        );
+        self.is_ended = animation.mode == Mode::Once;
+    }
+
+    fn seek(&mut self, animation: &Animation, frame_index: usize, elapsed_in_frame: Duration) {
+        self.animation_frame_index = frame_index;
+        self.sprite_frame_index = animation.frames[frame_index].index;
+        self.elapsed_in_frame = elapsed_in_frame;
+        self.is_ended = false;
+        self.is_seeked = true;
     }
 
     /// Returns the current frame index
@@ -32,61 +186,287 @@ impl State {
         self.sprite_frame_index
     }
 
+    /// Returns the position of the current frame among the animation's frames
+    #[must_use]
+    pub fn animation_frame_index(&self) -> usize {
+        self.animation_frame_index
+    }
+
     /// Returns true if the animation has ended
     #[must_use]
     pub fn is_ended(&self) -> bool {
         self.is_ended
     }
 
+    /// Returns true once the first frame of the animation has been entered
+    #[must_use]
+    pub fn is_started(&self) -> bool {
+        self.is_started
+    }
+
+    /// Returns how far into its frames the animation is, from 0 to 1
+    ///
+    /// Frames are counted in playback order, from the last one when reversed. Repeating
+    /// animations go back to 0 when they loop, and a finished animation is at 1.
+    #[must_use]
+    pub fn normalized_time(&self, animation: &Animation) -> f32 {
+        if self.is_ended {
+            return 1.0;
+        }
+
+        let total: Duration = animation.frames.iter().map(|frame| frame.duration).sum();
+        if total.is_zero() {
+            return 0.0;
+        }
+        let elapsed: Duration = animation
+            .frames
+            .iter()
+            .enumerate()
+            .filter(|(frame_index, _)| {
+                if self.is_reversed {
+                    *frame_index > self.animation_frame_index
+                } else {
+                    *frame_index < self.animation_frame_index
+                }
+            })
+            .map(|(_, frame)| frame.duration)
+            .sum::<Duration>()
+            + self.elapsed_in_frame;
+        (elapsed.as_secs_f32() / total.as_secs_f32()).min(1.0)
+    }
+
+    /// Returns how many times the animation looped since it started
+    #[must_use]
+    pub fn loop_count(&self) -> usize {
+        self.loop_count
+    }
+
+    /// Clamp the state into the frame range of `animation`
+    ///
+    /// Used when the animation changed underneath the state, e.g. when its clip was hot-reloaded.
+    pub fn clamp(&mut self, animation: &Animation) {
+        debug_assert!(animation.has_frames());
+        self.animation_frame_index = self.animation_frame_index.min(animation.frames.len() - 1);
+        if animation.mode != Mode::PingPong {
+            self.going_backward = false;
+        }
+        if animation.mode != Mode::Once {
+            self.is_ended = false;
+        }
+
+        let frame = self.frame(animation);
+        self.sprite_frame_index = frame.index;
+        self.elapsed_in_frame = self.elapsed_in_frame.min(frame.duration);
+    }
+
     #[must_use]
     fn frame<'a>(&self, animation: &'a Animation) -> &'a Frame {
         &animation.frames[self.animation_frame_index % animation.frames.len()]
     }
 
+    /// Move to the next frame in playback order
+    fn step(&mut self, animation: &Animation) {
+        let last_frame_index = animation.frames.len() - 1;
+        let index = self.animation_frame_index;
+        match animation.mode {
+            Mode::RepeatFrom(loop_from) => {
+                if !self.is_reversed && index >= last_frame_index {
+                    self.animation_frame_index = loop_from;
+                    self.loop_count += 1;
+                } else if self.is_reversed && (index == loop_from || index == 0) {
+                    self.animation_frame_index = last_frame_index;
+                    self.loop_count += 1;
+                } else if self.is_reversed {
+                    self.animation_frame_index -= 1;
+                } else {
+                    self.animation_frame_index += 1;
+                }
+            }
+            Mode::PingPong => {
+                // Cycles start from the first frame, or from the last one when reversed, and
+                // `going_backward` means heading back there
+                let (origin, far) = if self.is_reversed {
+                    (last_frame_index, 0)
+                } else {
+                    (0, last_frame_index)
+                };
+                let mut target = if self.going_backward { origin } else { far };
+                if index == target {
+                    self.going_backward = !self.going_backward;
+                    if target == origin {
+                        self.loop_count += 1;
+                    }
+                    target = if self.going_backward { origin } else { far };
+                }
+                if index < target {
+                    self.animation_frame_index += 1;
+                } else if index > target {
+                    self.animation_frame_index -= 1;
+                }
+            }
+            Mode::Once => {
+                let on_last_frame = if self.is_reversed {
+                    index == 0
+                } else {
+                    index >= last_frame_index
+                };
+                if on_last_frame {
+                    self.is_ended = true;
+                } else if self.is_reversed {
+                    self.animation_frame_index -= 1;
+                } else {
+                    self.animation_frame_index += 1;
+                }
+            }
+        }
+    }
+
     /// Update the animation state
     #[allow(dead_code)]
     pub fn update(&mut self, animation: &Animation, delta: Duration) {
+        self.update_with_events(animation, delta, |_| {});
+    }
+
+    /// Update the animation state, calling `on_event` for the events of every frame entered
+    ///
+    /// Frames that are skipped over within a single large `delta` still have their events sent,
+    /// in playback order.
+    pub fn update_with_events<'a>(
+        &mut self,
+        animation: &'a Animation,
+        delta: Duration,
+        mut on_event: impl FnMut(&'a Cow<'static, str>),
+    ) {
         debug_assert!(animation.has_frames());
+        if !self.is_started && self.is_reversed && !self.is_seeked {
+            self.animation_frame_index = animation.frames.len() - 1;
+        }
         let mut frame = self.frame(animation);
         self.sprite_frame_index = frame.index;
-        self.elapsed_in_frame += delta;
+        if !self.is_started {
+            self.is_started = true;
+            frame.events.iter().for_each(&mut on_event);
+        }
+        if self.is_paused {
+            return;
+        }
+        self.elapsed_in_frame += delta.mul_f32(self.speed);
         while self.elapsed_in_frame >= frame.duration {
-            let on_last_frame = self.animation_frame_index >= animation.frames.len() - 1;
-            match animation.mode {
-                Mode::RepeatFrom(loop_from) => {
-                    if on_last_frame {
-                        self.animation_frame_index = loop_from;
-                    } else {
-                        self.animation_frame_index += 1;
-                    }
-                }
-                Mode::PingPong => {
-                    if self.going_backward {
-                        if self.animation_frame_index == 0 {
-                            self.going_backward = false;
-                            self.animation_frame_index += 1;
-                        } else {
-                            self.animation_frame_index -= 1;
-                        }
-                    } else if on_last_frame {
-                        self.going_backward = true;
-                        self.animation_frame_index -= 1;
-                    } else {
-                        self.animation_frame_index += 1;
-                    }
-                }
-                Mode::Once => {
-                    if on_last_frame {
-                        self.is_ended = true;
-                    } else {
-                        self.animation_frame_index += 1;
-                    }
-                }
-            }
+            self.step(animation);
 
             self.elapsed_in_frame -= frame.duration;
             frame = self.frame(animation);
             self.sprite_frame_index = frame.index;
+
+            // A finished one-shot animation stays on its last frame without re-entering it
+            if !self.is_ended {
+                frame.events.iter().for_each(&mut on_event);
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::FrameRate;
+
+    const FRAME: Duration = Duration::from_millis(100);
+
+    fn four_frames() -> Animation {
+        Animation::from_indices(10..14, FrameRate::from_frame_duration(FRAME))
+    }
+
+    fn reversed() -> State {
+        let mut state = State::new();
+        state.set_reversed(true);
+        state
+    }
+
+    #[test]
+    fn reversed_one_shots_end_on_their_first_frame() {
+        let animation = four_frames().once();
+        let mut state = reversed();
+
+        state.update(&animation, Duration::ZERO);
+        assert_eq!(state.frame_index(), 13);
+        state.update(&animation, FRAME * 2);
+        assert_eq!(state.frame_index(), 11);
+        assert!((state.normalized_time(&animation) - 0.5).abs() < 1e-6);
+
+        state.update(&animation, FRAME * 2);
+        assert_eq!(state.frame_index(), 10);
+        assert!(state.is_ended());
+        assert_eq!(state.normalized_time(&animation), 1.0);
+    }
+
+    #[test]
+    fn reversed_loops_repeat_their_repeated_frames() {
+        let animation = four_frames().repeat_from(2);
+        let mut state = reversed();
+
+        let mut frames = Vec::new();
+        for _ in 0..5 {
+            state.update(&animation, FRAME);
+            frames.push(state.frame_index());
+        }
+        assert_eq!(frames, [12, 13, 12, 13, 12]);
+        assert_eq!(state.loop_count(), 2);
+        assert!(!state.is_ended());
+    }
+
+    #[test]
+    fn seeking_to_the_end_finishes_one_shots() {
+        let animation = four_frames().once();
+        let mut state = State::new();
+        state.seek_to_normalized_time(&animation, 1.0);
+        assert_eq!(state.frame_index(), 13);
+        assert!(state.is_ended());
+
+        let mut state = reversed();
+        state.seek_to_normalized_time(&animation, 1.0);
+        assert_eq!(state.frame_index(), 10);
+        assert!(state.is_ended());
+
+        // Playing again from before the end
+        state.seek_to_normalized_time(&animation, 0.3);
+        assert_eq!(state.frame_index(), 12);
+        assert!(!state.is_ended());
+        assert!((state.normalized_time(&animation) - 0.3).abs() < 1e-6);
+
+        let looping = four_frames().repeat();
+        let mut state = State::new();
+        state.seek_to_normalized_time(&looping, 1.0);
+        assert!(!state.is_ended());
+    }
+
+    #[test]
+    fn seeking_nan_goes_to_the_start() {
+        let animation = four_frames().once();
+        let mut state = State::new();
+        state.seek_to_normalized_time(&animation, 0.8);
+        state.seek_to_normalized_time(&animation, f32::NAN);
+        assert_eq!(state.frame_index(), 10);
+        assert_eq!(state.normalized_time(&animation), 0.0);
+    }
+
+    #[test]
+    fn seeked_reversed_animations_start_where_seeked() {
+        let animation = four_frames().once();
+        let mut state = reversed();
+        state.seek_to_frame(&animation, 1);
+        state.update(&animation, Duration::ZERO);
+        assert_eq!(state.frame_index(), 11);
+        assert!(state.is_started());
+    }
+
+    #[test]
+    fn huge_speeds_are_clamped() {
+        let animation = four_frames().repeat();
+        let mut state = State::new();
+        state.set_speed(f32::MAX);
+        assert_eq!(state.speed(), MAX_SPEED);
+        state.update(&animation, Duration::from_secs(1));
+        assert_eq!(state.loop_count(), 250);
+    }
+}
@@ -1,6 +1,8 @@
 use crate::player::Player;
+use crate::time_scale::ScaledTime;
 use bevy::prelude::*;
 
+/// Fraction of the distance to the player covered every 1/60th of a second
 const CAMERA_SMOOTHING_FACTOR: f32 = 0.2;
 
 pub struct CameraPlugin;
@@ -12,18 +14,19 @@ impl Plugin for CameraPlugin {
 }
 
 fn camera_follow_system(
+    time: ScaledTime,
     mut camera_query: Query<&mut Transform, (With<Camera>, Without<Player>)>,
     mut player_query: Query<&Transform, (With<Player>, Without<Camera>)>,
 ) {
     for player_transform in player_query.iter_mut() {
         for mut camera_transform in camera_query.iter_mut() {
-            // Use lerp for camera smoothing
+            // Use lerp for camera smoothing, independent of the frame rate
+            let smoothing =
+                1.0 - (1.0 - CAMERA_SMOOTHING_FACTOR).powf(time.delta_seconds(None) * 60.0);
             camera_transform.translation.x = camera_transform.translation.x
-                + (player_transform.translation.x - camera_transform.translation.x)
-                    * CAMERA_SMOOTHING_FACTOR;
+                + (player_transform.translation.x - camera_transform.translation.x) * smoothing;
             camera_transform.translation.y = camera_transform.translation.y
-                + (player_transform.translation.y - camera_transform.translation.y)
-                    * CAMERA_SMOOTHING_FACTOR;
+                + (player_transform.translation.y - camera_transform.translation.y) * smoothing;
         }
     }
 }
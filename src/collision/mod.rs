@@ -0,0 +1,545 @@
+pub mod spatial_hash;
+pub mod velocity;
+
+use crate::tilemap::{TileLayer, TileMap};
+use crate::time_scale::TimeScalePlugin;
+use crate::util::iso::{IsoGrid, WorldPosition};
+use bevy::prelude::*;
+use spatial_hash::{SpatialHash, SpatialHashPlugin};
+use velocity::ImpulseEvent;
+
+pub struct CollisionPlugin;
+
+impl Plugin for CollisionPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<TimeScalePlugin>() {
+            app.add_plugin(TimeScalePlugin);
+        }
+
+        app.add_plugin(SpatialHashPlugin::<Collider>::default())
+            .add_event::<ImpulseEvent>()
+            .add_systems(
+                (
+                    velocity::apply_impulses_system,
+                    velocity::integrate_velocity_system,
+                    move_kinematic_bodies_system,
+                )
+                    .chain()
+                    .in_set(CollisionSet),
+            );
+    }
+}
+
+/// The systems moving kinematic bodies. Systems setting their motion run before it.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CollisionSet;
+
+/// Shape of an entity on the ground plane, in world space around its `WorldPosition`
+///
+/// Colliders only block anything on a `StaticBody` or a `KinematicBody`.
+#[derive(Component, Debug, Clone, PartialEq)]
+pub enum Collider {
+    Aabb {
+        half_extents: Vec2,
+    },
+    Circle {
+        radius: f32,
+    },
+    /// A convex polygon, its vertices in order around it
+    Polygon {
+        vertices: Vec<Vec2>,
+    },
+}
+
+impl Collider {
+    #[must_use]
+    pub fn aabb(size: Vec2) -> Self {
+        Self::Aabb {
+            half_extents: size / 2.0,
+        }
+    }
+
+    #[must_use]
+    pub fn circle(radius: f32) -> Self {
+        Self::Circle { radius }
+    }
+
+    /// Returns `None` unless the vertices make a convex polygon, in either winding
+    #[must_use]
+    pub fn polygon(vertices: Vec<Vec2>) -> Option<Self> {
+        if vertices.len() < 3 {
+            return None;
+        }
+        let turns = (0..vertices.len()).map(|i| {
+            let [a, b, c] = [0, 1, 2].map(|offset| vertices[(i + offset) % vertices.len()]);
+            (b - a).perp_dot(c - b)
+        });
+        let (min_turn, max_turn) = turns.fold((f32::MAX, f32::MIN), |(min, max), turn| {
+            (min.min(turn), max.max(turn))
+        });
+        (min_turn > 0.0 || max_turn < 0.0).then_some(Self::Polygon { vertices })
+    }
+
+    /// A tile of `grid`, e.g. a wall
+    #[must_use]
+    pub fn tile(grid: &IsoGrid) -> Self {
+        Self::aabb(Vec2::splat(grid.tile_width))
+    }
+
+    /// Returns the collider rotated so that its x axis points along `direction`
+    #[must_use]
+    pub fn rotated(&self, direction: Vec2) -> Self {
+        let Some(direction) = direction.try_normalize() else {
+            return self.clone();
+        };
+        match self {
+            Collider::Circle { .. } => self.clone(),
+            _ => Collider::Polygon {
+                vertices: self
+                    .vertices(Vec2::ZERO)
+                    .unwrap()
+                    .into_iter()
+                    .map(|vertex| direction.rotate(vertex))
+                    .collect(),
+            },
+        }
+    }
+
+    /// Returns the vertices of the collider at `position`, or `None` for a circle
+    fn vertices(&self, position: Vec2) -> Option<Vec<Vec2>> {
+        match self {
+            Collider::Aabb { half_extents } => Some(
+                [
+                    Vec2::new(-1.0, -1.0),
+                    Vec2::new(1.0, -1.0),
+                    Vec2::new(1.0, 1.0),
+                    Vec2::new(-1.0, 1.0),
+                ]
+                .map(|corner| position + corner * *half_extents)
+                .to_vec(),
+            ),
+            Collider::Circle { .. } => None,
+            Collider::Polygon { vertices } => {
+                Some(vertices.iter().map(|vertex| position + *vertex).collect())
+            }
+        }
+    }
+
+    /// Returns the smallest and largest projection of the collider at `position` on `axis`
+    fn project(&self, position: Vec2, axis: Vec2) -> (f32, f32) {
+        match self {
+            Collider::Circle { radius } => {
+                let center = position.dot(axis);
+                (center - radius, center + radius)
+            }
+            _ => self
+                .vertices(position)
+                .unwrap()
+                .into_iter()
+                .map(|vertex| vertex.dot(axis))
+                .fold((f32::MAX, f32::MIN), |(min, max), projection| {
+                    (min.min(projection), max.max(projection))
+                }),
+        }
+    }
+
+    /// Returns the axes separating the collider from `other` if they don't overlap
+    fn separating_axes(&self, position: Vec2, other: &Collider, other_position: Vec2) -> Vec<Vec2> {
+        match self {
+            Collider::Aabb { .. } => vec![Vec2::X, Vec2::Y],
+            Collider::Circle { .. } => {
+                // The axis towards the closest vertex of the other polygon
+                let vertices = other.vertices(other_position).unwrap_or_default();
+                let closest = vertices.into_iter().min_by(|a, b| {
+                    a.distance_squared(position)
+                        .total_cmp(&b.distance_squared(position))
+                });
+                closest
+                    .and_then(|vertex| (vertex - position).try_normalize())
+                    .into_iter()
+                    .collect()
+            }
+            Collider::Polygon { vertices } => (0..vertices.len())
+                .filter_map(|i| {
+                    let edge = vertices[(i + 1) % vertices.len()] - vertices[i];
+                    edge.perp().try_normalize()
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns half the collider's smallest width, the furthest it can move in one step without
+    /// skipping over an obstacle
+    fn half_width(&self) -> f32 {
+        match self {
+            Collider::Aabb { half_extents } => half_extents.min_element(),
+            Collider::Circle { radius } => *radius,
+            Collider::Polygon { .. } => self
+                .separating_axes(Vec2::ZERO, self, Vec2::ZERO)
+                .into_iter()
+                .map(|axis| {
+                    let (min, max) = self.project(Vec2::ZERO, axis);
+                    (max - min) / 2.0
+                })
+                .fold(f32::MAX, f32::min),
+        }
+    }
+
+    /// Returns the distance along a ray to where it enters the collider at `position`, and the
+    /// collider's normal there. A ray starting inside the collider hits it at distance 0.
+    ///
+    /// `direction` must be normalized.
+    #[must_use]
+    pub fn raycast(&self, position: Vec2, origin: Vec2, direction: Vec2) -> Option<(f32, Vec2)> {
+        if let Collider::Circle { radius } = self {
+            let offset = origin - position;
+            let inside = offset.length_squared() - radius * radius;
+            if inside <= 0.0 {
+                return Some((0.0, -direction));
+            }
+            let projection = offset.dot(direction);
+            let discriminant = projection * projection - inside;
+            if projection > 0.0 || discriminant < 0.0 {
+                return None;
+            }
+            let distance = -projection - discriminant.sqrt();
+            let normal = (offset + direction * distance) / *radius;
+            return Some((distance, normal));
+        }
+
+        // Clip the ray by the half-planes of every edge, it hits where it enters the last one
+        let vertices = self.vertices(position).unwrap();
+        let center = vertices.iter().sum::<Vec2>() / vertices.len() as f32;
+        let (mut enter, mut exit) = (f32::NEG_INFINITY, f32::INFINITY);
+        let mut enter_normal = -direction;
+        for i in 0..vertices.len() {
+            let edge = vertices[(i + 1) % vertices.len()] - vertices[i];
+            let Some(mut normal) = edge.perp().try_normalize() else {
+                continue;
+            };
+            if normal.dot(vertices[i] - center) < 0.0 {
+                normal = -normal;
+            }
+            let approach = normal.dot(direction);
+            let distance = normal.dot(vertices[i] - origin);
+            if approach == 0.0 {
+                if distance < 0.0 {
+                    return None;
+                }
+            } else if approach < 0.0 {
+                if distance / approach > enter {
+                    enter = distance / approach;
+                    enter_normal = normal;
+                }
+            } else {
+                exit = exit.min(distance / approach);
+            }
+        }
+        if enter > exit || exit < 0.0 {
+            return None;
+        }
+        Some(if enter > 0.0 {
+            (enter, enter_normal)
+        } else {
+            (0.0, -direction)
+        })
+    }
+
+    /// Returns the bounding box of the collider at `position`
+    #[must_use]
+    pub fn bounds(&self, position: Vec2) -> Rect {
+        let (min_x, max_x) = self.project(position, Vec2::X);
+        let (min_y, max_y) = self.project(position, Vec2::Y);
+        Rect::new(min_x, min_y, max_x, max_y)
+    }
+
+    /// Returns the shortest translation moving the collider at `position` out of `other`, or
+    /// `None` if they don't overlap
+    #[must_use]
+    pub fn penetration(
+        &self,
+        position: Vec2,
+        other: &Collider,
+        other_position: Vec2,
+    ) -> Option<Vec2> {
+        if let (
+            Collider::Circle { radius },
+            Collider::Circle {
+                radius: other_radius,
+            },
+        ) = (self, other)
+        {
+            let offset = position - other_position;
+            let depth = radius + other_radius - offset.length();
+            return (depth > 0.0).then(|| offset.try_normalize().unwrap_or(Vec2::X) * depth);
+        }
+
+        // Separating axis theorem: the colliders overlap if their projections overlap on every
+        // axis, and are pushed apart along the axis they overlap the least on
+        let mut translation = Vec2::ZERO;
+        let mut depth = f32::INFINITY;
+        let axes = self
+            .separating_axes(position, other, other_position)
+            .into_iter()
+            .chain(other.separating_axes(other_position, self, position));
+        for axis in axes {
+            let (min, max) = self.project(position, axis);
+            let (other_min, other_max) = other.project(other_position, axis);
+            let (backward, forward) = (max - other_min, other_max - min);
+            if backward <= 0.0 || forward <= 0.0 {
+                return None;
+            }
+            if backward.min(forward) < depth {
+                depth = backward.min(forward);
+                translation = if backward < forward {
+                    -axis * backward
+                } else {
+                    axis * forward
+                };
+            }
+        }
+        depth.is_finite().then_some(translation)
+    }
+}
+
+/// An immobile collider, blocking kinematic bodies
+#[derive(Component, Debug, Default)]
+pub struct StaticBody;
+
+/// A collider moved by the game rather than pushed around, e.g. a character
+///
+/// Systems add to its `motion`, which `move_kinematic_bodies_system` then applies, sliding
+/// along the static colliders and the walls of tile maps. Kinematic bodies don't block each
+/// other.
+#[derive(Component, Debug, Default)]
+pub struct KinematicBody {
+    /// How far the body should move in world space this frame, reset once moved
+    pub motion: Vec2,
+    /// Normal of the last obstacle the body ran into during its last move, if any
+    pub contact_normal: Option<Vec2>,
+}
+
+/// Most steps a move is split into, so a huge motion can't stall the frame. Moves needing more
+/// may tunnel.
+const MAX_MOVE_STEPS: usize = 64;
+
+/// What kinematic bodies collide with: colliders and wall tiles
+pub struct Obstacles<'a> {
+    pub grid: &'a IsoGrid,
+    pub colliders: &'a SpatialHash<Collider>,
+    /// Whether a collider of `colliders` blocks kinematic bodies, e.g. if it's static
+    pub is_obstacle: &'a dyn Fn(Entity) -> bool,
+    pub tile_maps: Vec<&'a TileMap>,
+}
+
+impl Obstacles<'_> {
+    /// Pushes a collider at `position` out of every obstacle, returning its new position and the
+    /// last push
+    fn resolve(&self, collider: &Collider, mut position: Vec2) -> (Vec2, Option<Vec2>) {
+        let mut last_push = None;
+        let mut push_out = |position: &mut Vec2, obstacle: &Collider, obstacle_position| {
+            if let Some(push) = collider.penetration(*position, obstacle, obstacle_position) {
+                *position += push;
+                last_push = Some(push);
+            }
+        };
+
+        let candidates = self.colliders.candidates(collider.bounds(position));
+        for entity in candidates {
+            if let Some((obstacle, obstacle_position)) = self.colliders.get(entity) {
+                if (self.is_obstacle)(entity) {
+                    push_out(&mut position, obstacle, obstacle_position);
+                }
+            }
+        }
+
+        let wall = Collider::tile(self.grid);
+        for tile_map in &self.tile_maps {
+            let bounds = collider.bounds(position);
+            let min_tile = self.grid.world_to_tile(bounds.min);
+            let max_tile = self.grid.world_to_tile(bounds.max);
+            for y in min_tile.y..=max_tile.y {
+                for x in min_tile.x..=max_tile.x {
+                    let tile = IVec2::new(x, y);
+                    if tile_map.get(TileLayer::Walls, tile).is_some() {
+                        push_out(&mut position, &wall, self.grid.tile_to_world(tile));
+                    }
+                }
+            }
+        }
+
+        (position, last_push)
+    }
+
+    /// Moves a collider from `start` by `motion`, sliding along the obstacles it runs into.
+    /// Returns where it stopped, and the normal of the last obstacle it ran into.
+    ///
+    /// The motion is split into steps of at most half the collider's width, so fast moves such
+    /// as dashes can't tunnel through thin obstacles.
+    #[must_use]
+    pub fn move_and_slide(
+        &self,
+        collider: &Collider,
+        start: Vec2,
+        motion: Vec2,
+    ) -> (Vec2, Option<Vec2>) {
+        // A degenerate collider can't be skipped over by anything, so it moves in one step
+        let half_width = collider.half_width();
+        let step_count = if half_width > 0.0 {
+            (motion.length() / half_width)
+                .ceil()
+                .clamp(1.0, MAX_MOVE_STEPS as f32) as usize
+        } else {
+            1
+        };
+        let mut step = motion / step_count as f32;
+        let mut position = start;
+        let mut contact_normal = None;
+        for _ in 0..step_count {
+            let (resolved, push) = self.resolve(collider, position + step);
+            position = resolved;
+            if let Some(normal) = push.and_then(Vec2::try_normalize) {
+                // Keep moving along the obstacle, not into it
+                step -= normal * step.dot(normal).min(0.0);
+                contact_normal = Some(normal);
+            }
+        }
+        (position, contact_normal)
+    }
+}
+
+/// Applies the motion of every kinematic body
+pub fn move_kinematic_bodies_system(
+    grid: Res<IsoGrid>,
+    spatial_hash: Res<SpatialHash<Collider>>,
+    static_query: Query<(), With<StaticBody>>,
+    tile_map_query: Query<&TileMap>,
+    mut body_query: Query<(&Collider, &mut KinematicBody, &mut WorldPosition), Without<StaticBody>>,
+) {
+    let obstacles = Obstacles {
+        grid: &grid,
+        colliders: &spatial_hash,
+        is_obstacle: &|entity| static_query.contains(entity),
+        tile_maps: tile_map_query.iter().collect(),
+    };
+
+    for (collider, mut body, mut world_position) in body_query.iter_mut() {
+        let motion = std::mem::take(&mut body.motion);
+        if motion == Vec2::ZERO {
+            body.contact_normal = None;
+            continue;
+        }
+        let (position, contact_normal) =
+            obstacles.move_and_slide(collider, world_position.0, motion);
+        body.contact_normal = contact_normal;
+        world_position.0 = position;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    #[test]
+    fn penetration_pushes_out_along_the_shallowest_axis() {
+        let wall = Collider::aabb(Vec2::new(100.0, 20.0));
+        let circle = Collider::circle(10.0);
+        let push = circle
+            .penetration(Vec2::new(30.0, 15.0), &wall, Vec2::ZERO)
+            .unwrap();
+        assert!(push.abs_diff_eq(Vec2::new(0.0, 5.0), EPSILON), "{push}");
+        assert_eq!(
+            circle.penetration(Vec2::new(30.0, 25.0), &wall, Vec2::ZERO),
+            None
+        );
+
+        let triangle =
+            Collider::polygon(vec![Vec2::ZERO, Vec2::new(40.0, 0.0), Vec2::new(0.0, 40.0)])
+                .unwrap();
+        // Just past the hypotenuse
+        let push = circle
+            .penetration(Vec2::new(25.0, 25.0), &triangle, Vec2::ZERO)
+            .unwrap();
+        let depth = 10.0 - (50.0 - 40.0) / 2f32.sqrt();
+        assert!(
+            push.abs_diff_eq(Vec2::splat(depth / 2f32.sqrt()), EPSILON),
+            "{push}"
+        );
+    }
+
+    #[test]
+    fn concave_polygons_are_rejected() {
+        let arrow = vec![
+            Vec2::ZERO,
+            Vec2::new(10.0, 5.0),
+            Vec2::new(0.0, 10.0),
+            Vec2::new(3.0, 5.0),
+        ];
+        assert_eq!(Collider::polygon(arrow), None);
+    }
+
+    #[test]
+    fn bodies_slide_along_walls() {
+        let grid = IsoGrid::default();
+        let wall = Collider::aabb(Vec2::new(1000.0, 20.0));
+        let mut colliders = SpatialHash::default();
+        colliders.insert(Entity::from_raw(0), wall, Vec2::ZERO);
+        let obstacles = Obstacles {
+            grid: &grid,
+            colliders: &colliders,
+            is_obstacle: &|_| true,
+            tile_maps: Vec::new(),
+        };
+        let (position, contact_normal) = obstacles.move_and_slide(
+            &Collider::circle(10.0),
+            Vec2::new(0.0, 30.0),
+            Vec2::new(50.0, -50.0),
+        );
+        assert!(
+            position.abs_diff_eq(Vec2::new(50.0, 20.0), EPSILON),
+            "{position}"
+        );
+        assert_eq!(contact_normal, Some(Vec2::Y));
+    }
+
+    #[test]
+    fn fast_bodies_dont_tunnel() {
+        let grid = IsoGrid::default();
+        let wall = Collider::aabb(Vec2::new(4.0, 1000.0));
+        let mut colliders = SpatialHash::default();
+        colliders.insert(Entity::from_raw(0), wall, Vec2::ZERO);
+        let obstacles = Obstacles {
+            grid: &grid,
+            colliders: &colliders,
+            is_obstacle: &|_| true,
+            tile_maps: Vec::new(),
+        };
+        let (position, _) = obstacles.move_and_slide(
+            &Collider::circle(10.0),
+            Vec2::new(-100.0, 0.0),
+            Vec2::new(500.0, 0.0),
+        );
+        assert!(
+            position.abs_diff_eq(Vec2::new(-12.0, 0.0), EPSILON),
+            "{position}"
+        );
+    }
+
+    #[test]
+    fn degenerate_colliders_move_in_one_step() {
+        let grid = IsoGrid::default();
+        let colliders = SpatialHash::default();
+        let obstacles = Obstacles {
+            grid: &grid,
+            colliders: &colliders,
+            is_obstacle: &|_| true,
+            tile_maps: Vec::new(),
+        };
+        for collider in [Collider::circle(0.0), Collider::aabb(Vec2::new(10.0, 0.0))] {
+            let (position, _) = obstacles.move_and_slide(&collider, Vec2::ZERO, Vec2::X * 100.0);
+            assert_eq!(position, Vec2::X * 100.0);
+        }
+    }
+}
@@ -0,0 +1,404 @@
+use super::{Collider, CollisionSet};
+use crate::util::iso::WorldPosition;
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use std::marker::PhantomData;
+
+/// Keeps a `SpatialHash<T>` of every entity with a `T` and a `WorldPosition` up to date
+pub struct SpatialHashPlugin<T>(PhantomData<T>);
+
+impl<T> Default for SpatialHashPlugin<T> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T: SpatialShape> Plugin for SpatialHashPlugin<T> {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SpatialHash<T>>().add_system(
+            update_spatial_hash_system::<T>
+                .in_set(SpatialHashSet)
+                .after(CollisionSet),
+        );
+    }
+}
+
+/// The systems updating the spatial hashes, once the kinematic bodies moved. Systems querying
+/// them for this frame's positions run after it.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SpatialHashSet;
+
+/// A component with a shape, which a `SpatialHash` can index
+pub trait SpatialShape: Component {
+    fn collider(&self) -> &Collider;
+}
+
+impl SpatialShape for Collider {
+    fn collider(&self) -> &Collider {
+        self
+    }
+}
+
+/// Size of the cells of a spatial hash, in world units
+pub const DEFAULT_CELL_SIZE: f32 = 128.0;
+
+/// The shapes of the entities with a `T`, bucketed in a uniform grid of cells so queries only
+/// test the entities near them
+///
+/// Each entity is in every cell its bounding box overlaps. Cells are kept small enough that
+/// few entities share one, and large enough that most entities span few.
+#[derive(Resource)]
+pub struct SpatialHash<T> {
+    cell_size: f32,
+    cells: HashMap<IVec2, Vec<Entity>>,
+    entries: HashMap<Entity, SpatialEntry>,
+    /// Smallest and largest cell holding an entity, so rays stop once past them. It may be
+    /// larger than needed after removals, it's only reset once the hash is empty.
+    occupied: Option<(IVec2, IVec2)>,
+    marker: PhantomData<T>,
+}
+
+struct SpatialEntry {
+    collider: Collider,
+    position: Vec2,
+    min_cell: IVec2,
+    max_cell: IVec2,
+}
+
+/// Where a ray hit an entity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit {
+    pub entity: Entity,
+    /// Distance from the ray's origin, 0 if it started inside the entity
+    pub distance: f32,
+    pub point: Vec2,
+    /// Normal of the entity's surface at `point`
+    pub normal: Vec2,
+}
+
+impl<T> Default for SpatialHash<T> {
+    fn default() -> Self {
+        Self::new(DEFAULT_CELL_SIZE)
+    }
+}
+
+impl<T> SpatialHash<T> {
+    #[must_use]
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::default(),
+            entries: HashMap::default(),
+            occupied: None,
+            marker: PhantomData,
+        }
+    }
+
+    fn cell(&self, point: Vec2) -> IVec2 {
+        (point / self.cell_size).floor().as_ivec2()
+    }
+
+    fn cells_in(min_cell: IVec2, max_cell: IVec2) -> impl Iterator<Item = IVec2> {
+        (min_cell.y..=max_cell.y)
+            .flat_map(move |y| (min_cell.x..=max_cell.x).map(move |x| IVec2::new(x, y)))
+    }
+
+    /// Add an entity, or move it if it's already in
+    pub fn insert(&mut self, entity: Entity, collider: Collider, position: Vec2) {
+        let bounds = collider.bounds(position);
+        let (min_cell, max_cell) = (self.cell(bounds.min), self.cell(bounds.max));
+        if let Some(entry) = self.entries.get_mut(&entity) {
+            if entry.min_cell == min_cell && entry.max_cell == max_cell {
+                entry.collider = collider;
+                entry.position = position;
+                return;
+            }
+            self.remove(entity);
+        }
+
+        for cell in Self::cells_in(min_cell, max_cell) {
+            self.cells.entry(cell).or_default().push(entity);
+        }
+        self.occupied = Some(match self.occupied {
+            Some((occupied_min, occupied_max)) => {
+                (occupied_min.min(min_cell), occupied_max.max(max_cell))
+            }
+            None => (min_cell, max_cell),
+        });
+        self.entries.insert(
+            entity,
+            SpatialEntry {
+                collider,
+                position,
+                min_cell,
+                max_cell,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, entity: Entity) {
+        let Some(entry) = self.entries.remove(&entity) else {
+            return;
+        };
+        for cell in Self::cells_in(entry.min_cell, entry.max_cell) {
+            if let Some(entities) = self.cells.get_mut(&cell) {
+                entities.retain(|other| *other != entity);
+                if entities.is_empty() {
+                    self.cells.remove(&cell);
+                }
+            }
+        }
+        if self.entries.is_empty() {
+            self.occupied = None;
+        }
+    }
+
+    /// Returns the shape and position of an entity
+    #[must_use]
+    pub fn get(&self, entity: Entity) -> Option<(&Collider, Vec2)> {
+        self.entries
+            .get(&entity)
+            .map(|entry| (&entry.collider, entry.position))
+    }
+
+    /// Returns the entities in the cells overlapping `bounds`, each once. They may not overlap
+    /// `bounds` themselves.
+    #[must_use]
+    pub fn candidates(&self, bounds: Rect) -> Vec<Entity> {
+        let mut seen = HashSet::default();
+        Self::cells_in(self.cell(bounds.min), self.cell(bounds.max))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+            .filter(|entity| seen.insert(*entity))
+            .collect()
+    }
+
+    /// Returns the entities overlapping a collider at `position`
+    #[must_use]
+    pub fn query_collider(&self, collider: &Collider, position: Vec2) -> Vec<Entity> {
+        self.candidates(collider.bounds(position))
+            .into_iter()
+            .filter(|entity| {
+                let entry = &self.entries[entity];
+                collider
+                    .penetration(position, &entry.collider, entry.position)
+                    .is_some()
+            })
+            .collect()
+    }
+
+    /// Returns the entities containing a point
+    #[must_use]
+    pub fn query_point(&self, point: Vec2) -> Vec<Entity> {
+        self.query_collider(&Collider::circle(0.0), point)
+    }
+
+    /// Returns the entities overlapping a box
+    #[must_use]
+    pub fn query_aabb(&self, aabb: Rect) -> Vec<Entity> {
+        self.query_collider(&Collider::aabb(aabb.size()), aabb.center())
+    }
+
+    /// Returns the entities overlapping a circle
+    #[must_use]
+    pub fn query_circle(&self, center: Vec2, radius: f32) -> Vec<Entity> {
+        self.query_collider(&Collider::circle(radius), center)
+    }
+
+    /// Returns the first entity along a ray, within `max_distance` of its origin
+    ///
+    /// Walks the cells the ray crosses in order, so it stops as soon as it hits something
+    /// rather than testing every entity along a long ray, or once it leaves the occupied cells.
+    #[must_use]
+    pub fn raycast(&self, origin: Vec2, direction: Vec2, max_distance: f32) -> Option<RayHit> {
+        let direction = direction.try_normalize()?;
+        let mut cell = self.cell(origin);
+        let step = IVec2::new(direction.x.signum() as i32, direction.y.signum() as i32);
+        // Distance along the ray to the next cell boundary on each axis, and between boundaries
+        let next_boundary = (cell + step.max(IVec2::ZERO)).as_vec2() * self.cell_size;
+        let mut boundary_distance = Vec2::select(
+            direction.cmpeq(Vec2::ZERO),
+            Vec2::splat(f32::INFINITY),
+            (next_boundary - origin) / direction,
+        );
+        let boundary_spacing = (self.cell_size / direction).abs();
+
+        let mut tested = HashSet::default();
+        let mut closest: Option<RayHit> = None;
+        loop {
+            for entity in self.cells.get(&cell).into_iter().flatten() {
+                if !tested.insert(*entity) {
+                    continue;
+                }
+                let entry = &self.entries[entity];
+                let Some((distance, normal)) =
+                    entry.collider.raycast(entry.position, origin, direction)
+                else {
+                    continue;
+                };
+                if distance <= max_distance
+                    && !closest.is_some_and(|closest| closest.distance <= distance)
+                {
+                    closest = Some(RayHit {
+                        entity: *entity,
+                        distance,
+                        point: origin + direction * distance,
+                        normal,
+                    });
+                }
+            }
+
+            // Entities in the cells further along can only be hit further away
+            let cell_exit = boundary_distance.min_element();
+            if cell_exit > max_distance
+                || closest.is_some_and(|closest| closest.distance <= cell_exit)
+                || !self.may_reach_occupied(cell, step)
+            {
+                return closest;
+            }
+            if boundary_distance.x < boundary_distance.y {
+                cell.x += step.x;
+                boundary_distance.x += boundary_spacing.x;
+            } else {
+                cell.y += step.y;
+                boundary_distance.y += boundary_spacing.y;
+            }
+        }
+    }
+}
+
+impl<T> SpatialHash<T> {
+    /// Whether a ray walking from `cell` by `step` can still enter an occupied cell
+    fn may_reach_occupied(&self, cell: IVec2, step: IVec2) -> bool {
+        let Some((min_cell, max_cell)) = self.occupied else {
+            return false;
+        };
+        let past_max = cell.cmpgt(max_cell) & step.cmpge(IVec2::ZERO);
+        let past_min = cell.cmplt(min_cell) & step.cmple(IVec2::ZERO);
+        !(past_max | past_min).any()
+    }
+}
+
+/// Indexes the entities whose `T` or position changed, and drops the ones that lost their `T`
+#[allow(clippy::type_complexity)]
+pub fn update_spatial_hash_system<T: SpatialShape>(
+    mut spatial_hash: ResMut<SpatialHash<T>>,
+    mut removed: RemovedComponents<T>,
+    query: Query<(Entity, &T, &WorldPosition), Or<(Changed<T>, Changed<WorldPosition>)>>,
+) {
+    for entity in removed.iter() {
+        spatial_hash.remove(entity);
+    }
+    for (entity, shape, world_position) in query.iter() {
+        spatial_hash.insert(entity, shape.collider().clone(), world_position.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn spatial_hash(colliders: &[(Collider, Vec2)]) -> (SpatialHash<Collider>, Vec<Entity>) {
+        let mut spatial_hash = SpatialHash::new(32.0);
+        let entities = (0..colliders.len() as u32)
+            .map(Entity::from_raw)
+            .collect::<Vec<_>>();
+        for (entity, (collider, position)) in entities.iter().zip(colliders) {
+            spatial_hash.insert(*entity, collider.clone(), *position);
+        }
+        (spatial_hash, entities)
+    }
+
+    #[test]
+    fn queries_return_overlapping_entities() {
+        let (spatial_hash, entities) = spatial_hash(&[
+            (Collider::circle(10.0), Vec2::ZERO),
+            (Collider::aabb(Vec2::new(100.0, 10.0)), Vec2::new(0.0, 50.0)),
+            (Collider::circle(5.0), Vec2::new(200.0, 200.0)),
+        ]);
+
+        assert_eq!(spatial_hash.query_point(Vec2::new(3.0, 3.0)), [entities[0]]);
+        assert_eq!(
+            spatial_hash.query_point(Vec2::new(-45.0, 52.0)),
+            [entities[1]]
+        );
+        assert!(spatial_hash.query_point(Vec2::new(100.0, 100.0)).is_empty());
+
+        let mut found = spatial_hash.query_circle(Vec2::new(0.0, 30.0), 25.0);
+        found.sort();
+        assert_eq!(found, [entities[0], entities[1]]);
+        assert_eq!(
+            spatial_hash.query_aabb(Rect::new(190.0, 190.0, 300.0, 300.0)),
+            [entities[2]]
+        );
+    }
+
+    #[test]
+    fn moved_and_removed_entities_leave_their_cells() {
+        let (mut spatial_hash, entities) = spatial_hash(&[(Collider::circle(10.0), Vec2::ZERO)]);
+        spatial_hash.insert(entities[0], Collider::circle(10.0), Vec2::new(500.0, 0.0));
+        assert!(spatial_hash.query_point(Vec2::ZERO).is_empty());
+        assert_eq!(
+            spatial_hash.query_point(Vec2::new(500.0, 0.0)),
+            [entities[0]]
+        );
+
+        spatial_hash.remove(entities[0]);
+        assert!(spatial_hash.query_point(Vec2::new(500.0, 0.0)).is_empty());
+        assert!(spatial_hash.cells.is_empty());
+    }
+
+    #[test]
+    fn raycasts_hit_the_closest_entity() {
+        let (spatial_hash, entities) = spatial_hash(&[
+            (Collider::circle(10.0), Vec2::new(300.0, 0.0)),
+            (Collider::aabb(Vec2::splat(20.0)), Vec2::new(100.0, 0.0)),
+            (Collider::circle(10.0), Vec2::new(-100.0, 0.0)),
+        ]);
+
+        let hit = spatial_hash.raycast(Vec2::ZERO, Vec2::X, 1000.0).unwrap();
+        assert_eq!(hit.entity, entities[1]);
+        assert!((hit.distance - 90.0).abs() < EPSILON);
+        assert_eq!(hit.normal, -Vec2::X);
+
+        let hit = spatial_hash
+            .raycast(Vec2::new(200.0, 0.0), Vec2::X, 1000.0)
+            .unwrap();
+        assert_eq!(hit.entity, entities[0]);
+        assert!(hit.point.abs_diff_eq(Vec2::new(290.0, 0.0), EPSILON));
+
+        assert_eq!(spatial_hash.raycast(Vec2::ZERO, Vec2::X, 50.0), None);
+        assert_eq!(spatial_hash.raycast(Vec2::ZERO, Vec2::Y, 1000.0), None);
+    }
+
+    #[test]
+    fn infinite_rays_that_miss_return() {
+        let (mut spatial_hash, entities) = spatial_hash(&[
+            (Collider::circle(10.0), Vec2::new(100.0, 0.0)),
+            (Collider::circle(10.0), Vec2::new(-100.0, 300.0)),
+        ]);
+        let direction = Vec2::new(1.0, -0.3);
+        assert_eq!(
+            spatial_hash.raycast(Vec2::ZERO, direction, f32::INFINITY),
+            None
+        );
+        assert_eq!(
+            spatial_hash.raycast(Vec2::new(-5000.0, 50.0), Vec2::Y, f32::INFINITY),
+            None
+        );
+        assert!(spatial_hash
+            .raycast(Vec2::new(-5000.0, 0.0), Vec2::X, f32::INFINITY)
+            .is_some());
+
+        for entity in entities {
+            spatial_hash.remove(entity);
+        }
+        assert_eq!(
+            spatial_hash.raycast(Vec2::ZERO, Vec2::X, f32::INFINITY),
+            None
+        );
+    }
+}
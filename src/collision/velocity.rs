@@ -0,0 +1,134 @@
+use super::KinematicBody;
+use crate::time_scale::{ScaledTime, TimeScaleOverride};
+use bevy::prelude::*;
+
+/// Below this speed, in world units per second, a velocity stops
+const MIN_SPEED: f32 = 1.0;
+
+/// Velocity given to a kinematic body by outside forces, e.g. knockback, on top of the motion
+/// its controller gives it
+///
+/// It decays with `friction`, and loses what goes into the obstacle the body ran into.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct Velocity {
+    /// In world space, per second
+    pub linear: Vec2,
+    /// Fraction of the velocity lost every second, as an exponential decay rate
+    pub friction: f32,
+    /// Ignores impulses while set, e.g. during the hyper armor of a heavy attack
+    pub immovable: bool,
+}
+
+impl Velocity {
+    #[must_use]
+    pub fn new(friction: f32) -> Self {
+        Self {
+            friction,
+            ..default()
+        }
+    }
+
+    /// Adds `impulse` to the velocity, unless the body is immovable
+    pub fn apply_impulse(&mut self, impulse: Vec2) {
+        if !self.immovable {
+            self.linear += impulse;
+        }
+    }
+
+    /// Returns how far the velocity moves the body over `delta_seconds`, and slows it down
+    pub fn integrate(&mut self, delta_seconds: f32) -> Vec2 {
+        // Moving by the decayed velocity's integral keeps the distance independent of the
+        // frame rate
+        let decay = (-self.friction * delta_seconds).exp();
+        let motion = if self.friction > 0.0 {
+            self.linear * (1.0 - decay) / self.friction
+        } else {
+            self.linear * delta_seconds
+        };
+        self.linear *= decay;
+        if self.linear.length_squared() < MIN_SPEED * MIN_SPEED {
+            self.linear = Vec2::ZERO;
+        }
+        motion
+    }
+}
+
+/// Shoves `target`, if it has a `Velocity`
+#[derive(Debug, Clone)]
+pub struct ImpulseEvent {
+    pub target: Entity,
+    /// Velocity added to the target, in world space
+    pub impulse: Vec2,
+}
+
+pub fn apply_impulses_system(
+    mut impulse_events: EventReader<ImpulseEvent>,
+    mut query: Query<&mut Velocity>,
+) {
+    for event in impulse_events.iter() {
+        if let Ok(mut velocity) = query.get_mut(event.target) {
+            velocity.apply_impulse(event.impulse);
+        }
+    }
+}
+
+/// Adds the velocities to the motion of their kinematic body
+pub fn integrate_velocity_system(
+    time: ScaledTime,
+    mut query: Query<(
+        &mut Velocity,
+        &mut KinematicBody,
+        Option<&TimeScaleOverride>,
+    )>,
+) {
+    for (mut velocity, mut body, time_scale_override) in query.iter_mut() {
+        if velocity.linear == Vec2::ZERO {
+            continue;
+        }
+        // Don't keep pushing into the wall the body was shoved against
+        if let Some(normal) = body.contact_normal {
+            let into_obstacle = velocity.linear.dot(normal);
+            if into_obstacle < 0.0 {
+                velocity.linear -= normal * into_obstacle;
+            }
+        }
+        body.motion += velocity.integrate(time.delta_seconds(time_scale_override));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-3;
+
+    #[test]
+    fn distance_does_not_depend_on_the_frame_rate() {
+        // Half a second of knockback
+        let travel = |frames: usize| {
+            let mut velocity = Velocity::new(8.0);
+            velocity.apply_impulse(Vec2::new(240.0, 0.0));
+            let distance: Vec2 = (0..frames)
+                .map(|_| velocity.integrate(0.5 / frames as f32))
+                .sum();
+            (distance, velocity.linear)
+        };
+
+        let (distance, remaining) = travel(15);
+        let (other_distance, other_remaining) = travel(72);
+        assert!(distance.abs_diff_eq(other_distance, EPSILON));
+        assert!(remaining.abs_diff_eq(other_remaining, EPSILON));
+        // Slowed down well short of the undamped distance
+        assert!(distance.x > 0.0 && distance.x < 240.0 * 0.5 / 2.0);
+    }
+
+    #[test]
+    fn immovable_bodies_ignore_impulses() {
+        let mut velocity = Velocity {
+            immovable: true,
+            ..Velocity::new(8.0)
+        };
+        velocity.apply_impulse(Vec2::X * 100.0);
+        assert_eq!(velocity.linear, Vec2::ZERO);
+    }
+}
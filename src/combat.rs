@@ -0,0 +1,297 @@
+use crate::animation::events::AnimationStarted;
+use crate::animation::sprite::{AnimationLookup, AnimationSpriteSheet};
+use crate::animation::AnimationSet;
+use crate::collision::spatial_hash::{
+    SpatialHash, SpatialHashPlugin, SpatialHashSet, SpatialShape,
+};
+use crate::collision::velocity::ImpulseEvent;
+use crate::collision::Collider;
+use crate::damage::{DamageApplied, DamageEvent, DamageKind, DamageStage, Invulnerable};
+use crate::player::input::CharacterState;
+use crate::util::iso::WorldPosition;
+use bevy::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+/// Lets the entities playing animations `T` hit the hurtboxes around them with their `Hitbox<T>`
+pub struct CombatPlugin<T>(PhantomData<T>);
+
+impl<T> Default for CombatPlugin<T> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T> Plugin for CombatPlugin<T>
+where
+    T: Default + Eq + Copy + Hash + AnimationLookup<T> + Send + Sync + 'static,
+{
+    fn build(&self, app: &mut App) {
+        // Shared by the combat plugins of every animation enum
+        if !app.is_plugin_added::<SpatialHashPlugin<Hurtbox>>() {
+            app.add_plugin(SpatialHashPlugin::<Hurtbox>::default())
+                .add_system(knockback_system.after(DamageStage::Apply));
+        }
+
+        app.add_system(
+            hitbox_system::<T>
+                .after(AnimationSet)
+                .after(SpatialHashSet)
+                .before(DamageStage::Collect),
+        )
+        .add_system(
+            invulnerable_frames_system::<T>
+                .after(AnimationSet)
+                .before(DamageStage::Collect),
+        );
+    }
+}
+
+/// Where an entity can be hit, around its `WorldPosition`
+#[derive(Component, Debug, Clone)]
+pub struct Hurtbox {
+    pub collider: Collider,
+}
+
+impl SpatialShape for Hurtbox {
+    fn collider(&self) -> &Collider {
+        &self.collider
+    }
+}
+
+/// An attack, hitting with its collider in front of the attacker
+#[derive(Debug, Clone)]
+pub struct Attack {
+    /// Shape of the attack facing along +x, rotated to the attacker's facing
+    pub collider: Collider,
+    /// Distance from the attacker's position to the collider's center
+    pub reach: f32,
+    pub damage: f32,
+    pub kind: DamageKind,
+    pub poise_damage: f32,
+    /// Speed the targets are pushed away from the attacker at
+    pub knockback: f32,
+}
+
+impl Attack {
+    /// A physical attack, without poise damage or knockback
+    #[must_use]
+    pub fn new(collider: Collider, reach: f32, damage: f32) -> Self {
+        Self {
+            collider,
+            reach,
+            damage,
+            kind: DamageKind::Physical,
+            poise_damage: 0.0,
+            knockback: 0.0,
+        }
+    }
+
+    #[must_use]
+    pub fn with_kind(mut self, kind: DamageKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    #[must_use]
+    pub fn with_poise_damage(mut self, poise_damage: f32) -> Self {
+        self.poise_damage = poise_damage;
+        self
+    }
+
+    #[must_use]
+    pub fn with_knockback(mut self, knockback: f32) -> Self {
+        self.knockback = knockback;
+        self
+    }
+
+    /// Returns the collider of the attack and its center, for an attacker at `position` facing
+    /// `facing`
+    #[must_use]
+    pub fn placed(&self, position: Vec2, facing: Vec2) -> (Collider, Vec2) {
+        let facing = facing.try_normalize().unwrap_or(Vec2::X);
+        (
+            self.collider.rotated(facing),
+            position + facing * self.reach,
+        )
+    }
+}
+
+/// The attacks of an entity playing animations `T`
+///
+/// An attack hits while its animation plays a frame tagged `hitbox`, facing the attacker's
+/// `CharacterState::last_move_direction`. Each swing hits a hurtbox at most once, however many
+/// frames it overlaps it for.
+#[derive(Component, Debug)]
+pub struct Hitbox<T> {
+    pub attacks: HashMap<T, Attack>,
+    /// The attack hitting this frame, if any
+    active: Option<T>,
+    /// Entities already hit by the current swing
+    hit: HashSet<Entity>,
+}
+
+impl<T> Hitbox<T> {
+    #[must_use]
+    pub fn new(attacks: HashMap<T, Attack>) -> Self {
+        Self {
+            attacks,
+            active: None,
+            hit: HashSet::new(),
+        }
+    }
+}
+
+impl<T: Copy> Hitbox<T> {
+    /// Returns the attack hitting this frame, if any
+    #[must_use]
+    pub fn active(&self) -> Option<T> {
+        self.active
+    }
+}
+
+/// Activates the hitboxes on the tagged frames of their attacks, and sends a `DamageEvent` for
+/// every hurtbox they overlap
+#[allow(clippy::type_complexity)]
+pub fn hitbox_system<T>(
+    mut started_events: EventReader<AnimationStarted<T>>,
+    hurtboxes: Res<SpatialHash<Hurtbox>>,
+    mut attacker_query: Query<(
+        Entity,
+        &mut Hitbox<T>,
+        &AnimationSpriteSheet<T>,
+        &CharacterState,
+        &WorldPosition,
+    )>,
+    mut damage_events: EventWriter<DamageEvent>,
+) where
+    T: Default + Eq + Copy + Hash + AnimationLookup<T> + Send + Sync + 'static,
+{
+    // A new swing can hit everything again, even when it's the same attack restarted
+    for event in started_events.iter() {
+        if let Ok((_, mut hitbox, ..)) = attacker_query.get_mut(event.entity) {
+            hitbox.hit.clear();
+        }
+    }
+
+    for (entity, mut hitbox, sprite_sheet, character, world_position) in attacker_query.iter_mut() {
+        let animation = sprite_sheet.current_animation;
+        let attack = hitbox
+            .attacks
+            .get(&animation)
+            .filter(|_| sprite_sheet.is_in_tagged_frame("hitbox"))
+            .cloned();
+        hitbox.active = attack.as_ref().map(|_| animation);
+        let Some(attack) = attack else {
+            continue;
+        };
+
+        let (collider, center) = attack.placed(world_position.0, character.last_move_direction);
+        for target in hurtboxes.query_collider(&collider, center) {
+            if target == entity || !hitbox.hit.insert(target) {
+                continue;
+            }
+            // Pushed away from the attacker, or along the swing if right on top of it
+            let away = hurtboxes
+                .get(target)
+                .and_then(|(_, position)| (position - world_position.0).try_normalize())
+                .unwrap_or(character.last_move_direction.normalize_or_zero());
+            damage_events.send(DamageEvent {
+                source: Some(entity),
+                target,
+                amount: attack.damage,
+                kind: attack.kind,
+                poise_damage: attack.poise_damage,
+                knockback: away * attack.knockback,
+            });
+        }
+    }
+}
+
+/// Makes the entities playing a frame tagged `invulnerable` invulnerable for that frame, e.g.
+/// the i-frames of a dodge roll
+pub fn invulnerable_frames_system<T>(
+    mut commands: Commands,
+    query: Query<(Entity, &AnimationSpriteSheet<T>), Without<Invulnerable>>,
+) where
+    T: Default + Eq + Copy + Hash + AnimationLookup<T> + Send + Sync + 'static,
+{
+    for (entity, sprite_sheet) in query.iter() {
+        if sprite_sheet.is_in_tagged_frame("invulnerable") {
+            commands.entity(entity).insert(Invulnerable::default());
+        }
+    }
+}
+
+/// Shoves the targets of the hits that landed
+pub fn knockback_system(
+    mut applied_events: EventReader<DamageApplied>,
+    mut impulse_events: EventWriter<ImpulseEvent>,
+) {
+    for event in applied_events.iter() {
+        if event.knockback != Vec2::ZERO {
+            impulse_events.send(ImpulseEvent {
+                target: event.target,
+                impulse: event.knockback,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::damage::DamagePlugin;
+    use crate::health::Health;
+    use crate::player::animation::PlayerAnimation;
+    use std::time::Duration;
+
+    #[test]
+    fn invulnerable_frames_ignore_hits() {
+        let mut app = App::new();
+        app.init_resource::<Time>()
+            .add_plugin(DamagePlugin)
+            .add_system(invulnerable_frames_system::<PlayerAnimation>.before(DamageStage::Collect));
+
+        // Into the second frame of the dash, tagged `invulnerable`
+        let mut sprite_sheet = AnimationSpriteSheet {
+            current_animation: PlayerAnimation::Dash,
+            ..default()
+        };
+        sprite_sheet.update_state(Duration::from_millis(40));
+        assert!(sprite_sheet.is_in_tagged_frame("invulnerable"));
+        let target = app
+            .world
+            .spawn((
+                Health {
+                    current: 100,
+                    max: 100,
+                },
+                sprite_sheet,
+            ))
+            .id();
+
+        let hit = |app: &mut App| {
+            app.world.send_event(DamageEvent {
+                source: None,
+                target,
+                amount: 10.0,
+                kind: DamageKind::Physical,
+                poise_damage: 0.0,
+                knockback: Vec2::ZERO,
+            });
+            app.update();
+            app.world.get::<Health>(target).unwrap().current
+        };
+        assert_eq!(hit(&mut app), 100);
+        // Still protected on the next frame, as long as the tagged frame shows
+        assert_eq!(hit(&mut app), 100);
+
+        app.world
+            .get_mut::<AnimationSpriteSheet<PlayerAnimation>>(target)
+            .unwrap()
+            .force_animation(PlayerAnimation::Idle);
+        assert_eq!(hit(&mut app), 90);
+    }
+}
@@ -0,0 +1,392 @@
+use crate::health::{deal_damage, Health};
+use crate::time_scale::{ScaledTime, TimeScaleOverride, TimeScalePlugin};
+use bevy::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+/// Turns `DamageEvent`s into health loss, through the `DamageStage`s, and sends a
+/// `DamageApplied` for every hit that landed
+pub struct DamagePlugin;
+
+impl Plugin for DamagePlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<TimeScalePlugin>() {
+            app.add_plugin(TimeScalePlugin);
+        }
+
+        app.add_event::<DamageEvent>()
+            .add_event::<DamageApplied>()
+            .init_resource::<PendingDamage>()
+            .configure_sets(
+                (
+                    DamageStage::Collect,
+                    DamageStage::Invulnerability,
+                    DamageStage::Buffs,
+                    DamageStage::Resistances,
+                    DamageStage::Armor,
+                    DamageStage::Apply,
+                )
+                    .chain(),
+            )
+            .add_system(tick_invulnerability_system.in_base_set(CoreSet::PreUpdate))
+            .add_system(collect_damage_system.in_set(DamageStage::Collect))
+            // Invulnerabilities granted through commands during the frame protect from its hits
+            .add_system(
+                apply_system_buffers
+                    .after(DamageStage::Collect)
+                    .before(DamageStage::Invulnerability),
+            )
+            .add_system(invulnerability_damage_system.in_set(DamageStage::Invulnerability))
+            .add_system(buff_damage_system.in_set(DamageStage::Buffs))
+            .add_system(resist_damage_system.in_set(DamageStage::Resistances))
+            .add_system(armor_damage_system.in_set(DamageStage::Armor))
+            .add_system(apply_damage_system.in_set(DamageStage::Apply))
+            .add_system(mercy_invulnerability_system.after(DamageStage::Apply));
+    }
+}
+
+/// The stages a hit goes through, in order. Systems in a stage adjust or cancel the hits in
+/// `PendingDamage`.
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DamageStage {
+    /// The `DamageEvent`s of the frame become pending hits
+    Collect,
+    /// Hits on targets that can't be hurt right now are cancelled. `Invulnerable` components
+    /// inserted before `Collect` count.
+    Invulnerability,
+    /// The attacker's and the target's `DamageModifiers`
+    Buffs,
+    /// The target's `Resistances` to the kind of damage
+    Resistances,
+    /// The target's `Armor`
+    Armor,
+    /// What's left is taken from the target's `Health`
+    Apply,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DamageKind {
+    Physical,
+    Magic,
+    Fire,
+    Lightning,
+}
+
+/// A request to hurt `target`, e.g. from a hitbox
+#[derive(Debug, Clone)]
+pub struct DamageEvent {
+    /// Who dealt the damage, if anyone
+    pub source: Option<Entity>,
+    pub target: Entity,
+    pub amount: f32,
+    pub kind: DamageKind,
+    pub poise_damage: f32,
+    /// Push given to the target, in world space
+    pub knockback: Vec2,
+}
+
+/// Sent for every hit that went through all the damage stages, for UI, audio and stats
+#[derive(Debug, Clone)]
+pub struct DamageApplied {
+    pub source: Option<Entity>,
+    pub target: Entity,
+    /// Health the target lost, after every modifier
+    pub amount: u32,
+    pub kind: DamageKind,
+    pub poise_damage: f32,
+    pub knockback: Vec2,
+    /// Whether the hit brought the target to 0 health
+    pub is_lethal: bool,
+}
+
+/// The hits of this frame being processed by the damage stages
+#[derive(Resource, Debug, Default)]
+pub struct PendingDamage(pub Vec<DamageEvent>);
+
+/// Multipliers of the damage an entity deals and takes, e.g. from buffs and debuffs
+#[derive(Component, Debug, Clone, Copy)]
+pub struct DamageModifiers {
+    pub outgoing: f32,
+    pub incoming: f32,
+}
+
+impl Default for DamageModifiers {
+    fn default() -> Self {
+        Self {
+            outgoing: 1.0,
+            incoming: 1.0,
+        }
+    }
+}
+
+/// Fraction of each kind of damage an entity ignores. Negative values are weaknesses.
+#[derive(Component, Debug, Clone, Default)]
+pub struct Resistances(pub HashMap<DamageKind, f32>);
+
+/// Flat damage reduction of every hit on the entity
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct Armor(pub f32);
+
+/// Makes the entity ignore every hit until it runs out
+///
+/// The time is scaled like the entity's animations, so a hitstop doesn't eat into i-frames.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct Invulnerable {
+    remaining: Duration,
+}
+
+impl Invulnerable {
+    /// Invulnerable for `duration`
+    ///
+    /// With `Duration::ZERO`, it's removed by the next frame's tick, so it only protects from the
+    /// hits of the frame it's inserted in, if that's before `DamageStage::Collect`.
+    #[must_use]
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            remaining: duration,
+        }
+    }
+
+    /// Keep the entity invulnerable for at least `duration`
+    ///
+    /// Invulnerabilities granted while one runs don't add up, the longest one wins.
+    pub fn extend(&mut self, duration: Duration) {
+        self.remaining = self.remaining.max(duration);
+    }
+
+    #[must_use]
+    pub fn remaining(&self) -> Duration {
+        self.remaining
+    }
+}
+
+/// Makes the entity `Invulnerable` for `duration` after each hit it takes, and lets only one hit
+/// a frame through, so a crowd of attackers can't stunlock it
+#[derive(Component, Debug, Clone, Copy)]
+pub struct MercyInvulnerability {
+    pub duration: Duration,
+}
+
+/// Fraction of a hit that always goes through armor, so armor never makes an entity immune
+const MIN_ARMORED_DAMAGE: f32 = 0.1;
+
+pub fn collect_damage_system(
+    mut events: EventReader<DamageEvent>,
+    mut pending_damage: ResMut<PendingDamage>,
+) {
+    pending_damage.0.extend(events.iter().cloned());
+}
+
+/// Cancels the hits on invulnerable targets, and all but the first hit on a target with mercy
+/// invulnerability
+pub fn invulnerability_damage_system(
+    mut pending_damage: ResMut<PendingDamage>,
+    invulnerable_query: Query<(), With<Invulnerable>>,
+    mercy_query: Query<(), With<MercyInvulnerability>>,
+) {
+    let mut hit_targets = HashSet::new();
+    pending_damage.0.retain(|damage| {
+        if invulnerable_query.contains(damage.target) {
+            return false;
+        }
+        hit_targets.insert(damage.target) || !mercy_query.contains(damage.target)
+    });
+}
+
+pub fn buff_damage_system(
+    mut pending_damage: ResMut<PendingDamage>,
+    query: Query<&DamageModifiers>,
+) {
+    for damage in pending_damage.0.iter_mut() {
+        if let Some(modifiers) = damage.source.and_then(|source| query.get(source).ok()) {
+            damage.amount *= modifiers.outgoing;
+        }
+        if let Ok(modifiers) = query.get(damage.target) {
+            damage.amount *= modifiers.incoming;
+        }
+    }
+}
+
+pub fn resist_damage_system(mut pending_damage: ResMut<PendingDamage>, query: Query<&Resistances>) {
+    for damage in pending_damage.0.iter_mut() {
+        if let Ok(resistances) = query.get(damage.target) {
+            let resistance = resistances.0.get(&damage.kind).copied().unwrap_or_default();
+            damage.amount *= 1.0 - resistance;
+        }
+    }
+}
+
+pub fn armor_damage_system(mut pending_damage: ResMut<PendingDamage>, query: Query<&Armor>) {
+    for damage in pending_damage.0.iter_mut() {
+        if let Ok(armor) = query.get(damage.target) {
+            damage.amount = (damage.amount - armor.0).max(damage.amount * MIN_ARMORED_DAMAGE);
+        }
+    }
+}
+
+pub fn apply_damage_system(
+    mut pending_damage: ResMut<PendingDamage>,
+    mut applied_events: EventWriter<DamageApplied>,
+    mut query: Query<&mut Health>,
+) {
+    for damage in pending_damage.0.drain(..) {
+        let Ok(mut health) = query.get_mut(damage.target) else {
+            continue;
+        };
+        let before = health.current;
+        deal_damage(&mut health, damage.amount.max(0.0).round() as u32);
+
+        applied_events.send(DamageApplied {
+            source: damage.source,
+            target: damage.target,
+            amount: before - health.current,
+            kind: damage.kind,
+            poise_damage: damage.poise_damage,
+            knockback: damage.knockback,
+            is_lethal: before > 0 && health.current == 0,
+        });
+    }
+}
+
+/// Makes the entities with mercy invulnerability invulnerable after being hit
+pub fn mercy_invulnerability_system(
+    mut commands: Commands,
+    mut applied_events: EventReader<DamageApplied>,
+    mut query: Query<(&MercyInvulnerability, Option<&mut Invulnerable>)>,
+) {
+    for event in applied_events.iter() {
+        let Ok((mercy, invulnerable)) = query.get_mut(event.target) else {
+            continue;
+        };
+        match invulnerable {
+            Some(mut invulnerable) => invulnerable.extend(mercy.duration),
+            None => {
+                commands
+                    .entity(event.target)
+                    .insert(Invulnerable::new(mercy.duration));
+            }
+        }
+    }
+}
+
+/// Counts down the invulnerabilities, removing them once they run out
+pub fn tick_invulnerability_system(
+    mut commands: Commands,
+    time: ScaledTime,
+    mut query: Query<(Entity, &mut Invulnerable, Option<&TimeScaleOverride>)>,
+) {
+    for (entity, mut invulnerable, time_scale_override) in query.iter_mut() {
+        invulnerable.remaining = invulnerable
+            .remaining
+            .saturating_sub(time.delta(time_scale_override));
+        if invulnerable.remaining.is_zero() {
+            commands.entity(entity).remove::<Invulnerable>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(app: &mut App, source: Option<Entity>, target: Entity, amount: f32) -> DamageApplied {
+        app.world.send_event(DamageEvent {
+            source,
+            target,
+            amount,
+            kind: DamageKind::Fire,
+            poise_damage: 0.0,
+            knockback: Vec2::ZERO,
+        });
+        app.update();
+        let events = app.world.resource::<Events<DamageApplied>>();
+        let applied = events.iter_current_update_events().last().cloned();
+        applied.expect("the hit wasn't applied")
+    }
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.init_resource::<Time>().add_plugin(DamagePlugin);
+        app
+    }
+
+    #[test]
+    fn stages_apply_in_order() {
+        let mut app = app();
+        let source = app
+            .world
+            .spawn(DamageModifiers {
+                outgoing: 2.0,
+                incoming: 1.0,
+            })
+            .id();
+        let target = app
+            .world
+            .spawn((
+                Health {
+                    current: 100,
+                    max: 100,
+                },
+                Resistances(HashMap::from([(DamageKind::Fire, 0.5)])),
+                Armor(5.0),
+            ))
+            .id();
+
+        // Doubled, halved, then armored
+        let applied = hit(&mut app, Some(source), target, 20.0);
+        assert_eq!(applied.amount, 15);
+        assert_eq!(app.world.get::<Health>(target).unwrap().current, 85);
+
+        // Armor can't block a whole hit
+        let applied = hit(&mut app, None, target, 10.0);
+        assert_eq!(applied.amount, 1);
+
+        let applied = hit(&mut app, None, target, 1000.0);
+        assert_eq!(applied.amount, 84);
+        assert!(applied.is_lethal);
+    }
+
+    #[test]
+    fn invulnerable_targets_ignore_hits() {
+        let mut app = app();
+        let target = app
+            .world
+            .spawn((
+                Health {
+                    current: 100,
+                    max: 100,
+                },
+                MercyInvulnerability {
+                    duration: Duration::from_secs(1),
+                },
+            ))
+            .id();
+
+        // Only the first of the hits landing together goes through
+        for _ in 0..3 {
+            app.world.send_event(DamageEvent {
+                source: None,
+                target,
+                amount: 10.0,
+                kind: DamageKind::Physical,
+                poise_damage: 0.0,
+                knockback: Vec2::ZERO,
+            });
+        }
+        app.update();
+        assert_eq!(app.world.get::<Health>(target).unwrap().current, 90);
+
+        // Then the mercy invulnerability holds
+        app.world.send_event(DamageEvent {
+            source: None,
+            target,
+            amount: 10.0,
+            kind: DamageKind::Physical,
+            poise_damage: 0.0,
+            knockback: Vec2::ZERO,
+        });
+        app.update();
+        assert_eq!(app.world.get::<Health>(target).unwrap().current, 90);
+        assert!(app.world.get::<Invulnerable>(target).is_some());
+    }
+}
@@ -0,0 +1,158 @@
+use crate::animation::library::AnimationLibrary;
+use crate::animation::machine::{AnimationStateMachine, Transition};
+use crate::animation::sprite::AnimationSpriteSheet;
+use crate::collision::velocity::Velocity;
+use crate::collision::{Collider, KinematicBody};
+use crate::combat::Hurtbox;
+use crate::health::Health;
+use crate::player::animation::{DirectionAtlasHandles, PlayerAnimation, SPRITE_COLUMNS};
+use crate::poise::{Poise, Stagger};
+use crate::respawn::Rested;
+use crate::souls::SoulReward;
+use crate::tilemap::tiled::EnemySpawn;
+use crate::util::iso::WorldPosition;
+use crate::util::Direction;
+use crate::ysort::YSort;
+use bevy::prelude::*;
+
+pub struct EnemyPlugin;
+
+impl Plugin for EnemyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems((
+            spawn_enemies_system,
+            despawn_orphaned_enemies_system,
+            reset_enemies_system,
+            enemy_death_system,
+        ));
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct Enemy {
+    /// The `EnemySpawn` the enemy came from
+    pub spawn: Entity,
+}
+
+/// Spawns an enemy at every new enemy spawn of the map
+pub fn spawn_enemies_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    direction_atlas_handles: Res<DirectionAtlasHandles>,
+    spawn_query: Query<(Entity, &WorldPosition), Added<EnemySpawn>>,
+) {
+    for (spawn, world_position) in spawn_query.iter() {
+        spawn_enemy(
+            &mut commands,
+            &asset_server,
+            &direction_atlas_handles,
+            spawn,
+            *world_position,
+        );
+    }
+}
+
+/// Brings every enemy back to its spawn, alive and well, when the player rests
+pub fn reset_enemies_system(
+    mut commands: Commands,
+    mut rested_events: EventReader<Rested>,
+    asset_server: Res<AssetServer>,
+    direction_atlas_handles: Res<DirectionAtlasHandles>,
+    spawn_query: Query<(Entity, &WorldPosition), With<EnemySpawn>>,
+    enemy_query: Query<Entity, With<Enemy>>,
+) {
+    if rested_events.is_empty() {
+        return;
+    }
+    rested_events.clear();
+
+    for entity in enemy_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    for (spawn, world_position) in spawn_query.iter() {
+        spawn_enemy(
+            &mut commands,
+            &asset_server,
+            &direction_atlas_handles,
+            spawn,
+            *world_position,
+        );
+    }
+}
+
+/// Spawns an enemy at `spawn`
+///
+/// Until enemies get sprites of their own, they are a tinted copy of the player.
+fn spawn_enemy(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    direction_atlas_handles: &DirectionAtlasHandles,
+    spawn: Entity,
+    world_position: WorldPosition,
+) {
+    let Some((atlas_handle, flip_x)) = direction_atlas_handles.get(Direction::SouthWest) else {
+        return;
+    };
+    commands.spawn((
+        SpriteSheetBundle {
+            texture_atlas: atlas_handle.clone(),
+            sprite: TextureAtlasSprite {
+                color: Color::rgb(1.0, 0.45, 0.45),
+                flip_x,
+                ..default()
+            },
+            transform: Transform::from_scale(Vec3::splat(2.0)),
+            ..default()
+        },
+        AnimationSpriteSheet::<PlayerAnimation> {
+            column_count: SPRITE_COLUMNS,
+            ..default()
+        },
+        // Back to idle once staggered
+        AnimationStateMachine::new([Transition::from_any(PlayerAnimation::Idle)]),
+        asset_server.load::<AnimationLibrary, _>("animations/player.anim.ron"),
+        world_position,
+        Collider::circle(16.0),
+        KinematicBody::default(),
+        Velocity::new(8.0),
+        Hurtbox {
+            collider: Collider::circle(20.0),
+        },
+        YSort::default(),
+        (
+            Health {
+                current: 50,
+                max: 50,
+            },
+            Poise::new(20.0),
+            Stagger {
+                animation: PlayerAnimation::Stagger,
+            },
+            SoulReward(50),
+        ),
+        Enemy { spawn },
+    ));
+}
+
+/// Despawns the enemies whose spawn is gone, e.g. when the map is reloaded
+pub fn despawn_orphaned_enemies_system(
+    mut commands: Commands,
+    mut removed_spawns: RemovedComponents<EnemySpawn>,
+    enemy_query: Query<(Entity, &Enemy)>,
+) {
+    for spawn in removed_spawns.iter() {
+        for (entity, enemy) in enemy_query.iter() {
+            if enemy.spawn == spawn {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+}
+
+pub fn enemy_death_system(mut commands: Commands, query: Query<(Entity, &Health), With<Enemy>>) {
+    for (entity, health) in query.iter() {
+        if health.current == 0 {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
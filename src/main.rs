@@ -1,21 +1,63 @@
 mod animation;
 mod camera;
+mod collision;
+mod combat;
+mod damage;
+mod enemy;
 mod health;
 mod player;
+mod poise;
+mod respawn;
+mod souls;
+mod stamina;
+mod tilemap;
+mod time_scale;
 mod util;
+mod ysort;
 
+use animation::library::AnimationLibrary;
 use bevy::prelude::*;
-use bevy::sprite::MaterialMesh2dBundle;
+use collision::velocity::Velocity;
+use collision::{Collider, KinematicBody};
+use combat::{Hitbox, Hurtbox};
+use damage::MercyInvulnerability;
 use health::Health;
-use player::animation::DirectionAtlasHandles;
-use util::Direction;
+use player::animation::{
+    DirectionAtlasHandles, PlayerAnimation, SPRITE_COLUMNS, SPRITE_ROWS, SPRITE_SIZE,
+};
+use poise::{Poise, Stagger};
+use souls::Souls;
+use stamina::Stamina;
+use std::path::Path;
+use std::time::Duration;
+use tilemap::tiled::TiledMap;
+use util::iso::WorldPosition;
+use util::{Direction, DirectionQuantizer, Directions};
+use ysort::YSort;
 
 fn main() {
     App::new()
-        .add_plugins(DefaultPlugins.set(ImagePlugin::default_nearest()))
+        .add_plugins(
+            DefaultPlugins
+                .set(ImagePlugin::default_nearest())
+                .set(AssetPlugin {
+                    // Pick up animation and sprite sheet changes without restarting
+                    watch_for_changes: true,
+                    ..default()
+                }),
+        )
+        .add_plugin(time_scale::TimeScalePlugin)
+        .add_plugin(util::iso::IsoPlugin)
+        .add_plugin(collision::CollisionPlugin)
+        .add_plugin(damage::DamagePlugin)
+        .add_plugin(stamina::StaminaPlugin)
+        .add_plugin(tilemap::TileMapPlugin)
+        .add_plugin(ysort::YSortPlugin)
         .add_plugin(player::PlayerPlugin)
+        .add_plugin(enemy::EnemyPlugin)
         .add_plugin(camera::CameraPlugin)
-        .add_plugin(health::HealthPlugin)
+        .add_plugin(respawn::RespawnPlugin)
+        .add_plugin(souls::SoulsPlugin)
         .add_startup_system(setup)
         .run();
 }
@@ -25,21 +67,32 @@ fn setup(
     asset_server: Res<AssetServer>,
     mut texture_atlases: ResMut<Assets<TextureAtlas>>,
     mut direction_atlas_handles: ResMut<DirectionAtlasHandles>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
-    // Load the directional player textures
+    // Load the directional player textures that ship with the game, directions without a sheet
+    // mirror the opposite one
     let texture_handles = [
         ("HeroEast.png", Direction::East),
         ("HeroNorth.png", Direction::North),
         ("HeroNorthEast.png", Direction::NorthEast),
+        ("HeroNorthWest.png", Direction::NorthWest),
         ("HeroSouth.png", Direction::South),
         ("HeroSouthEast.png", Direction::SouthEast),
+        ("HeroSouthWest.png", Direction::SouthWest),
+        ("HeroWest.png", Direction::West),
     ];
     for (file_name, direction) in &texture_handles {
+        if !asset_server.asset_io().is_file(Path::new(file_name)) {
+            continue;
+        }
         let texture_handle = asset_server.load(*file_name);
-        let atlas =
-            TextureAtlas::from_grid(texture_handle, Vec2::new(64.0, 64.0), 10, 12, None, None);
+        let atlas = TextureAtlas::from_grid(
+            texture_handle,
+            SPRITE_SIZE,
+            SPRITE_COLUMNS,
+            SPRITE_ROWS,
+            None,
+            None,
+        );
         let atlas_handle = texture_atlases.add(atlas);
         direction_atlas_handles.0.insert(*direction, atlas_handle);
     }
@@ -61,50 +114,56 @@ fn setup(
         },
         player::input::CharacterState {
             speed: 300.0,
+            sprint_speed: 450.0,
             dashing: false,
             attacking: false,
             last_move_direction: Vec2::new(1.0, 0.0),
+            // The sprites face screen directions, whose diagonals follow the 2:1 tiles. A little
+            // hysteresis so a direction held near a boundary doesn't flicker.
+            direction_quantizer: DirectionQuantizer::isometric(Directions::Eight)
+                .with_hysteresis(0.1),
         },
+        WorldPosition::default(),
+        Collider::circle(16.0),
+        KinematicBody::default(),
+        Velocity::new(8.0),
+        YSort::default(),
         player::input::AttackState {
             ..Default::default()
         },
+        animation::machine::AnimationStateMachine::new(
+            player::animation::player_animation_transitions(),
+        ),
         animation::sprite::AnimationSpriteSheet::<player::animation::PlayerAnimation> {
-            column_count: 10,
+            column_count: SPRITE_COLUMNS,
+            via_animations: player::animation::player_via_animations(),
             ..Default::default()
         },
-        Health {
-            current: 100,
-            max: 100,
-        },
+        asset_server.load::<AnimationLibrary, _>("animations/player.anim.ron"),
+        // Combat, nested as bundles are limited to 15 components
+        (
+            Health {
+                current: 100,
+                max: 100,
+            },
+            Hurtbox {
+                collider: Collider::circle(20.0),
+            },
+            Hitbox::new(player::animation::player_attacks()),
+            MercyInvulnerability {
+                duration: Duration::from_millis(600),
+            },
+            Stamina::new(100.0),
+            player::animation::player_stamina_costs(),
+            Poise::new(40.0),
+            Stagger {
+                animation: PlayerAnimation::Stagger,
+            },
+        ),
+        Souls::default(),
         player::Player,
     ));
 
-    // Rectangle
-    commands.spawn(SpriteBundle {
-        sprite: Sprite {
-            color: Color::rgb(0.25, 0.25, 0.75),
-            custom_size: Some(Vec2::new(50.0, 100.0)),
-            ..default()
-        },
-        transform: Transform::from_translation(Vec3::new(-50., 0., 0.)),
-        ..default()
-    });
-
-    // Quad
-    commands.spawn(MaterialMesh2dBundle {
-        mesh: meshes
-            .add(shape::Quad::new(Vec2::new(50., 100.)).into())
-            .into(),
-        material: materials.add(ColorMaterial::from(Color::LIME_GREEN)),
-        transform: Transform::from_translation(Vec3::new(50., 0., 0.)),
-        ..default()
-    });
-
-    // Hexagon
-    commands.spawn(MaterialMesh2dBundle {
-        mesh: meshes.add(shape::RegularPolygon::new(50., 6).into()).into(),
-        material: materials.add(ColorMaterial::from(Color::TURQUOISE)),
-        transform: Transform::from_translation(Vec3::new(150., 0., 0.)),
-        ..default()
-    });
+    // The map, which moves the player to its spawn once loaded
+    commands.spawn(asset_server.load::<TiledMap, _>("maps/courtyard.tmx"));
 }
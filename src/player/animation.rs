@@ -1,9 +1,14 @@
-use crate::animation::{sprite::AnimationLookup, Animation, FrameRate};
+use crate::animation::machine::{AnimationStateMachine, Condition, Transition};
+use crate::animation::sprite::{AnimationLookup, AnimationSpriteSheet};
+use crate::animation::{Animation, FrameRate};
+use crate::collision::Collider;
+use crate::combat::Attack;
+use crate::stamina::StaminaCosts;
 use crate::util::Direction;
 use bevy::prelude::*;
 use std::collections::HashMap;
 
-#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum PlayerAnimation {
     Idle,
     Run,
@@ -13,6 +18,8 @@ pub enum PlayerAnimation {
     Attack1,
     Attack2,
     Attack3,
+    Stagger,
+    Death,
 }
 
 impl Default for PlayerAnimation {
@@ -21,33 +28,241 @@ impl Default for PlayerAnimation {
     }
 }
 
+/// How the player moves between animations
+///
+/// The `speed` parameter is the length of the movement input, and the `dash` and `attack`
+/// triggers are set when their button is pressed.
+pub fn player_animation_transitions() -> Vec<Transition<PlayerAnimation>> {
+    let dash = || Condition::Trigger("dash".into());
+    let attack = || Condition::Trigger("attack".into());
+
+    vec![
+        Transition::from_any(PlayerAnimation::Dash)
+            .when(dash())
+            .priority(3),
+        // Attacking during the second half of a dash turns it into a dash attack
+        Transition::new(PlayerAnimation::Dash, PlayerAnimation::DashAttack)
+            .when(attack())
+            .exit_time(0.5)
+            .priority(2),
+        // Attacking again during a swing chains into the next one once it's done
+        Transition::new(PlayerAnimation::Attack1, PlayerAnimation::Attack2)
+            .when(attack())
+            .exit_time(1.0)
+            .priority(2),
+        Transition::new(PlayerAnimation::Attack2, PlayerAnimation::Attack3)
+            .when(attack())
+            .exit_time(1.0)
+            .priority(2),
+        Transition::new(PlayerAnimation::Attack3, PlayerAnimation::Attack1)
+            .when(attack())
+            .exit_time(1.0)
+            .priority(2),
+        Transition::from_any(PlayerAnimation::Attack1)
+            .when(attack())
+            .priority(1),
+        Transition::from_any(PlayerAnimation::Run).when(Condition::Greater("speed".into(), 0.0)),
+        Transition::from_any(PlayerAnimation::Idle)
+            .when(Condition::Less("speed".into(), f32::EPSILON)),
+    ]
+}
+
+/// Animations played between two of the player's animations, keyed by `(from, to)`
+pub fn player_via_animations() -> HashMap<(PlayerAnimation, PlayerAnimation), PlayerAnimation> {
+    HashMap::from([(
+        (PlayerAnimation::Run, PlayerAnimation::Idle),
+        PlayerAnimation::RunStop,
+    )])
+}
+
+/// The player's attacks, active on the frames of their animation tagged `hitbox`
+pub fn player_attacks() -> HashMap<PlayerAnimation, Attack> {
+    HashMap::from([
+        (
+            PlayerAnimation::Attack1,
+            Attack::new(Collider::aabb(Vec2::new(40.0, 48.0)), 28.0, 10.0)
+                .with_poise_damage(10.0)
+                .with_knockback(120.0),
+        ),
+        (
+            PlayerAnimation::Attack2,
+            Attack::new(Collider::aabb(Vec2::new(40.0, 48.0)), 28.0, 12.0)
+                .with_poise_damage(12.0)
+                .with_knockback(120.0),
+        ),
+        (
+            PlayerAnimation::Attack3,
+            Attack::new(Collider::aabb(Vec2::new(56.0, 64.0)), 32.0, 20.0)
+                .with_poise_damage(25.0)
+                .with_knockback(240.0),
+        ),
+        (
+            PlayerAnimation::DashAttack,
+            Attack::new(Collider::circle(28.0), 24.0, 15.0)
+                .with_poise_damage(20.0)
+                .with_knockback(180.0),
+        ),
+    ])
+}
+
+/// What the player's actions cost
+pub fn player_stamina_costs() -> StaminaCosts<PlayerAnimation> {
+    StaminaCosts {
+        actions: HashMap::from([
+            (PlayerAnimation::Attack1, 15.0),
+            (PlayerAnimation::Attack2, 18.0),
+            (PlayerAnimation::Attack3, 25.0),
+            (PlayerAnimation::Dash, 20.0),
+            (PlayerAnimation::DashAttack, 22.0),
+        ]),
+        sprint: 20.0,
+    }
+}
+
+/// Prints the player's animation state machine when F1 is pressed
+pub fn dump_animation_state_machine_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    query: Query<(
+        &AnimationStateMachine<PlayerAnimation>,
+        &AnimationSpriteSheet<PlayerAnimation>,
+    )>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::F1) {
+        return;
+    }
+
+    for (state_machine, sprite_sheet) in query.iter() {
+        println!("{}", state_machine.dump(sprite_sheet));
+    }
+}
+
+/// Size of a single frame in the player sprite sheets
+pub const SPRITE_SIZE: Vec2 = Vec2::new(64.0, 64.0);
+pub const SPRITE_COLUMNS: usize = 10;
+pub const SPRITE_ROWS: usize = 12;
+
 // Resources
 #[derive(Default)]
 pub struct DirectionAtlasHandles(pub HashMap<Direction, Handle<TextureAtlas>>);
 // Implement the Resource trait for the newtype
 impl Resource for DirectionAtlasHandles {}
 
+impl DirectionAtlasHandles {
+    /// Returns the atlas to draw `direction` with, and whether it must be flipped horizontally
+    ///
+    /// A direction without its own sprite sheet mirrors the opposite one, e.g. West mirrors East.
+    #[must_use]
+    pub fn get(&self, direction: Direction) -> Option<(&Handle<TextureAtlas>, bool)> {
+        self.0
+            .get(&direction)
+            .map(|atlas_handle| (atlas_handle, false))
+            .or_else(|| {
+                self.0
+                    .get(&direction.mirrored())
+                    .map(|atlas_handle| (atlas_handle, true))
+            })
+    }
+}
+
+/// Rebuilds the direction atlases when their sprite sheet is hot-reloaded, so rows added to the
+/// sheet become addressable without restarting
+pub fn rebuild_direction_atlases_system(
+    mut events: EventReader<AssetEvent<Image>>,
+    images: Res<Assets<Image>>,
+    direction_atlas_handles: Res<DirectionAtlasHandles>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+) {
+    for event in events.iter() {
+        let AssetEvent::Modified { handle } = event else {
+            continue;
+        };
+        let Some(image) = images.get(handle) else {
+            continue;
+        };
+
+        for atlas_handle in direction_atlas_handles.0.values() {
+            let Some(atlas) = texture_atlases.get_mut(atlas_handle) else {
+                continue;
+            };
+            if atlas.texture != *handle {
+                continue;
+            }
+
+            // Keep the column layout the clip indices rely on, but follow the sheet's height
+            let rows = (image.size().y / SPRITE_SIZE.y) as usize;
+            *atlas = TextureAtlas::from_grid(
+                handle.clone(),
+                SPRITE_SIZE,
+                SPRITE_COLUMNS,
+                rows.max(1),
+                None,
+                None,
+            );
+        }
+    }
+}
+
 impl AnimationLookup<PlayerAnimation> for PlayerAnimation {
     fn get_animation(anim_enum: &PlayerAnimation) -> Animation {
         match anim_enum {
             PlayerAnimation::Idle => Animation::from_indices(0..=5, FrameRate::from_fps(12.0)),
-            PlayerAnimation::Run => Animation::from_indices(20..=25, FrameRate::from_fps(12.0)),
-            PlayerAnimation::RunStop => Animation::from_indices(30..=33, FrameRate::from_fps(12.0)),
-            PlayerAnimation::DashAttack => {
-                Animation::from_indices(80..=89, FrameRate::from_fps(12.0)).once()
-            }
-            PlayerAnimation::Dash => {
-                Animation::from_indices(80..=89, FrameRate::from_fps(30.0)).once()
-            }
-            PlayerAnimation::Attack1 => {
-                Animation::from_indices(90..=95, FrameRate::from_fps(12.0)).once()
+            PlayerAnimation::Run => Animation::from_indices(20..=25, FrameRate::from_fps(12.0))
+                .with_event(1, "footstep")
+                .with_event(4, "footstep"),
+            PlayerAnimation::RunStop => {
+                Animation::from_indices(30..=33, FrameRate::from_fps(12.0)).once()
             }
+            PlayerAnimation::DashAttack => (0..=6).fold(
+                Animation::from_indices(80..=89, FrameRate::from_fps(12.0))
+                    .once()
+                    .with_event(4, "hitbox")
+                    .with_event(5, "hitbox")
+                    .with_event(6, "hitbox"),
+                |animation, frame_index| animation.with_event(frame_index, "hyper_armor"),
+            ),
+            PlayerAnimation::Dash => (1..=6).fold(
+                Animation::from_indices(80..=89, FrameRate::from_fps(30.0)).once(),
+                |animation, frame_index| animation.with_event(frame_index, "invulnerable"),
+            ),
+            PlayerAnimation::Attack1 => Animation::from_indices(90..=95, FrameRate::from_fps(12.0))
+                .once()
+                .with_event(2, "hitbox")
+                .with_event(3, "hitbox"),
             PlayerAnimation::Attack2 => {
-                Animation::from_indices(100..=108, FrameRate::from_fps(24.0)).once()
+                Animation::from_indices(100..=108, FrameRate::from_fps(24.0))
+                    .once()
+                    .with_event(3, "hitbox")
+                    .with_event(4, "hitbox")
+                    .with_event(5, "hitbox")
+            }
+            PlayerAnimation::Attack3 => (0..=3).fold(
+                Animation::from_indices(110..=116, FrameRate::from_fps(24.0))
+                    .once()
+                    .with_event(2, "hitbox")
+                    .with_event(3, "hitbox"),
+                |animation, frame_index| animation.with_event(frame_index, "hyper_armor"),
+            ),
+            PlayerAnimation::Stagger => {
+                Animation::from_indices(40..=43, FrameRate::from_fps(8.0)).once()
             }
-            PlayerAnimation::Attack3 => {
-                Animation::from_indices(110..=116, FrameRate::from_fps(24.0)).once()
+            PlayerAnimation::Death => {
+                Animation::from_indices(60..=63, FrameRate::from_fps(8.0)).once()
             }
         }
     }
+
+    fn clip_name(anim_enum: &PlayerAnimation) -> &'static str {
+        match anim_enum {
+            PlayerAnimation::Idle => "Idle",
+            PlayerAnimation::Run => "Run",
+            PlayerAnimation::RunStop => "RunStop",
+            PlayerAnimation::Dash => "Dash",
+            PlayerAnimation::DashAttack => "DashAttack",
+            PlayerAnimation::Attack1 => "Attack1",
+            PlayerAnimation::Attack2 => "Attack2",
+            PlayerAnimation::Attack3 => "Attack3",
+            PlayerAnimation::Stagger => "Stagger",
+            PlayerAnimation::Death => "Death",
+        }
+    }
 }
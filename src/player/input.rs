@@ -1,6 +1,11 @@
+use crate::animation::machine::AnimationStateMachine;
 use crate::animation::sprite::AnimationSpriteSheet;
+use crate::collision::KinematicBody;
 use crate::player::animation::{DirectionAtlasHandles, PlayerAnimation};
-use crate::util::{direction_to_texture_atlas_direction, vec2_to_direction, Direction};
+use crate::stamina::{Stamina, StaminaCosts};
+use crate::time_scale::{ScaledTime, TimeScaleOverride};
+use crate::util::iso::IsoGrid;
+use crate::util::DirectionQuantizer;
 use bevy::prelude::*;
 
 #[derive(Resource, Default)]
@@ -8,6 +13,7 @@ pub struct InputState {
     move_direction: Vec2,
     attack: bool,
     dash: bool,
+    sprint: bool,
 }
 
 #[derive(Component, Default)]
@@ -18,161 +24,161 @@ pub struct AttackState {
 #[derive(Component)]
 pub struct CharacterState {
     pub speed: f32,
+    /// Speed while sprinting, which costs stamina
+    pub sprint_speed: f32,
     pub dashing: bool,
     pub attacking: bool,
+    /// In world space
     pub last_move_direction: Vec2,
+    /// Picks the facing direction from the movement
+    pub direction_quantizer: DirectionQuantizer,
 }
 
 pub fn character_controller_system(
-    time: Res<Time>,
+    time: ScaledTime,
     input_state: Res<InputState>,
-    direction_atlas_handles: ResMut<DirectionAtlasHandles>,
+    direction_atlas_handles: Res<DirectionAtlasHandles>,
+    grid: Res<IsoGrid>,
     mut query: Query<(
         &mut CharacterState,
-        &mut AnimationSpriteSheet<PlayerAnimation>,
-        &mut Transform,
+        &mut AttackState,
+        &mut AnimationStateMachine<PlayerAnimation>,
+        &AnimationSpriteSheet<PlayerAnimation>,
+        &mut KinematicBody,
         &mut Handle<TextureAtlas>,
         &mut TextureAtlasSprite,
+        &mut Stamina,
+        &StaminaCosts<PlayerAnimation>,
+        Option<&TimeScaleOverride>,
     )>,
 ) {
-    for (mut character, mut sprite_sheet, mut transform, mut atlas, mut sprite) in query.iter_mut()
+    for (
+        mut character,
+        mut attack_state,
+        mut state_machine,
+        sprite_sheet,
+        mut body,
+        mut atlas,
+        mut sprite,
+        mut stamina,
+        stamina_costs,
+        time_scale_override,
+    ) in query.iter_mut()
     {
-        // 8-directional movement
-        if !character.dashing && !character.attacking {
+        let delta_seconds = time.delta_seconds(time_scale_override);
+
+        // The state machine picks the animation, the character follows the one-shot it plays
+        let playing = sprite_sheet
+            .locked
+            .then_some(sprite_sheet.current_animation);
+
+        let dashing = playing == Some(PlayerAnimation::Dash);
+        if dashing != character.dashing {
+            println!(
+                "{}",
+                if dashing {
+                    "Start dashing!"
+                } else {
+                    "End dashing!"
+                }
+            );
+        }
+        character.dashing = dashing;
+
+        attack_state.current_attack = match playing {
+            Some(PlayerAnimation::Attack1) => 1,
+            Some(PlayerAnimation::Attack2) => 2,
+            Some(PlayerAnimation::Attack3) => 3,
+            _ => 0,
+        };
+        let attacking =
+            attack_state.current_attack != 0 || playing == Some(PlayerAnimation::DashAttack);
+        if attacking != character.attacking {
+            println!(
+                "{}",
+                if attacking {
+                    "Start attacking!"
+                } else {
+                    "End attacking!"
+                }
+            );
+        }
+        character.attacking = attacking;
+        let staggered = playing == Some(PlayerAnimation::Stagger);
+
+        // 8-directional movement, along the isometric axes of the world
+        if !character.dashing && !character.attacking && !staggered {
             if input_state.move_direction != Vec2::ZERO {
                 let move_direction = input_state.move_direction.normalize();
-                transform.translation +=
-                    move_direction.extend(0.0) * character.speed * time.delta_seconds();
+                let speed = if input_state.sprint && stamina.can_act() {
+                    stamina.spend(stamina_costs.sprint * delta_seconds);
+                    character.sprint_speed
+                } else {
+                    character.speed
+                };
+                body.motion += move_direction * speed * delta_seconds;
                 character.last_move_direction = move_direction;
 
-                let direction = vec2_to_direction(move_direction);
-                // Flip the sprite based on the current direction
-                sprite.flip_x = matches!(
-                    direction,
-                    Direction::SouthWest | Direction::West | Direction::NorthWest
-                );
-
-                let direction = direction_to_texture_atlas_direction(move_direction);
-                // Update the texture atlas based on the character's direction
-                if let Some(atlas_handle) = direction_atlas_handles.0.get(&direction) {
+                // Update the texture atlas based on the character's direction on screen,
+                // mirroring the opposite direction's when it has no sprite sheet of its own
+                let direction = character
+                    .direction_quantizer
+                    .quantize_direction(grid.world_to_screen(move_direction));
+                if let Some((atlas_handle, flip_x)) =
+                    direction.and_then(|direction| direction_atlas_handles.get(direction))
+                {
                     *atlas = atlas_handle.clone();
+                    sprite.flip_x = flip_x;
                 }
             }
         }
 
-        // Dash
-        if input_state.dash && !character.dashing {
-            character.dashing = true;
-            println!("Start dashing!");
-            sprite_sheet.set_animation(PlayerAnimation::Dash);
-        }
-
+        // Dash, stopping at walls like any other move
         if character.dashing {
-            // If it's not locked anymore, then the animation finished
-            if !sprite_sheet.locked {
-                character.dashing = false;
-                println!("End dashing!");
-            } else {
-                let move_direction = character.last_move_direction.normalize_or_zero();
+            let move_direction = character.last_move_direction.normalize_or_zero();
 
-                let dash_speed = character.speed * 2.0;
-                transform.translation +=
-                    move_direction.extend(0.0) * dash_speed * time.delta_seconds();
-            }
+            let dash_speed = character.speed * 2.0;
+            body.motion += move_direction * dash_speed * delta_seconds;
         }
 
-        // Attack
-        if input_state.attack && !character.attacking {
-            character.attacking = true;
-            println!("Start attacking!");
-            sprite_sheet.set_animation(PlayerAnimation::Attack1);
-        } else if input_state.attack
-            && character.attacking
-            && sprite_sheet.queued_animation.is_none()
-        {
-            // If the player is already attacking, then queue up the next attack
-            println!("Queued attack!");
-
-            // Chain attacks together if pressed while the player is still attacking
-            if sprite_sheet.current_animation == PlayerAnimation::Attack1 {
-                sprite_sheet.queued_animation = Some(PlayerAnimation::Attack2);
-            } else if sprite_sheet.current_animation == PlayerAnimation::Attack2 {
-                sprite_sheet.queued_animation = Some(PlayerAnimation::Attack3);
-            } else if sprite_sheet.current_animation == PlayerAnimation::Attack3 {
-                sprite_sheet.queued_animation = Some(PlayerAnimation::Attack1);
+        // Feed the input to the state machine, which chains attacks and buffers presses made
+        // during a locked animation. Actions are refused once exhausted, their stamina is spent
+        // when their animation starts.
+        state_machine
+            .parameters
+            .set_float("speed", input_state.move_direction.length());
+        if stamina.can_act() {
+            if input_state.dash {
+                state_machine.parameters.set_trigger("dash");
+            }
+            if input_state.attack {
+                state_machine.parameters.set_trigger("attack");
             }
-        } else if character.attacking && !sprite_sheet.locked {
-            // If it's not locked anymore, then the animation finished
-            character.attacking = false;
-            println!("End attacking!");
         }
     }
 }
 
 pub fn input_handling_system(
-    time: Res<Time>,
     keyboard_input: Res<Input<KeyCode>>,
     mut input_state: ResMut<InputState>,
-    mut query: Query<(
-        &CharacterState,
-        &mut AnimationSpriteSheet<PlayerAnimation>,
-        &mut TextureAtlasSprite,
-    )>,
 ) {
-    for (character, mut sprite_sheet, mut sprite) in query.iter_mut() {
-        sprite_sheet.update_state(time.delta());
-        sprite.index = sprite_sheet.state.frame_index();
-
-        // Get the attack input state regardless if the animation is locked or not
-        input_state.attack = keyboard_input.just_pressed(KeyCode::Space);
-
-        // If the animation is locked, don't change it until it's done
-        if sprite_sheet.locked {
-            if sprite_sheet.state.is_ended() {
-                println!("Animation ended! Unlocking...");
-                sprite_sheet.locked = false;
+    let mut move_direction = Vec2::ZERO;
 
-                if sprite_sheet.queued_animation.is_some() {
-                    let queued_animation = sprite_sheet.queued_animation.unwrap();
-                    sprite_sheet.set_animation(queued_animation);
-                    sprite_sheet.queued_animation = None;
-                } else {
-                    // This is needed otherwise the animation will be stuck on the last frame
-                    sprite_sheet.state.reset();
-                }
-            } else {
-                if character.attacking {
-                    println!("Attacking!");
-                } else if character.dashing {
-                    println!("Dashing!");
-                }
-
-                return;
-            }
-        }
-
-        let mut move_direction = Vec2::ZERO;
-
-        if keyboard_input.pressed(KeyCode::W) {
-            move_direction.y += 1.0;
-        }
-        if keyboard_input.pressed(KeyCode::S) {
-            move_direction.y -= 1.0;
-        }
-        if keyboard_input.pressed(KeyCode::A) {
-            move_direction.x -= 1.0;
-        }
-        if keyboard_input.pressed(KeyCode::D) {
-            move_direction.x += 1.0;
-        }
-
-        if move_direction == Vec2::ZERO {
-            sprite_sheet.set_animation(PlayerAnimation::Idle);
-        } else {
-            sprite_sheet.set_animation(PlayerAnimation::Run);
-        }
-
-        input_state.move_direction = move_direction;
-        input_state.dash = keyboard_input.just_pressed(KeyCode::LShift);
+    if keyboard_input.pressed(KeyCode::W) {
+        move_direction.y += 1.0;
     }
+    if keyboard_input.pressed(KeyCode::S) {
+        move_direction.y -= 1.0;
+    }
+    if keyboard_input.pressed(KeyCode::A) {
+        move_direction.x -= 1.0;
+    }
+    if keyboard_input.pressed(KeyCode::D) {
+        move_direction.x += 1.0;
+    }
+
+    input_state.move_direction = move_direction;
+    input_state.attack = keyboard_input.just_pressed(KeyCode::Space);
+    input_state.dash = keyboard_input.just_pressed(KeyCode::LShift);
+    input_state.sprint = keyboard_input.pressed(KeyCode::LControl);
 }
@@ -1,7 +1,13 @@
 pub mod animation;
 pub mod input;
 
-use bevy::{app::{App, Plugin}, ecs::component::Component};
+use crate::animation::{AnimationPlugin, AnimationSet};
+use crate::collision::CollisionSet;
+use crate::combat::CombatPlugin;
+use crate::poise::PoisePlugin;
+use crate::stamina::spend_action_stamina_system;
+use animation::PlayerAnimation;
+use bevy::{app::{App, Plugin}, ecs::component::Component, prelude::*};
 
 #[derive(Component)]
 pub struct Player;
@@ -10,9 +16,22 @@ pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<input::InputState>()
+        app.add_plugin(AnimationPlugin::<PlayerAnimation>::default())
+            .add_plugin(CombatPlugin::<PlayerAnimation>::default())
+            .add_plugin(PoisePlugin::<PlayerAnimation>::default())
+            .init_resource::<input::InputState>()
             .init_resource::<animation::DirectionAtlasHandles>()
-            .add_system(input::character_controller_system)
-            .add_system(input::input_handling_system);
+            .add_system(animation::rebuild_direction_atlases_system)
+            .add_system(animation::dump_animation_state_machine_system)
+            .add_system(spend_action_stamina_system::<PlayerAnimation>.after(AnimationSet))
+            .add_systems(
+                (
+                    input::input_handling_system,
+                    input::character_controller_system,
+                )
+                    .chain()
+                    .before(AnimationSet)
+                    .before(CollisionSet),
+            );
     }
 }
@@ -0,0 +1,169 @@
+use crate::animation::sprite::{AnimationLookup, AnimationSpriteSheet};
+use crate::animation::AnimationSet;
+use crate::collision::velocity::Velocity;
+use crate::damage::{DamageApplied, DamageStage};
+use crate::time_scale::{ScaledTime, TimeScaleOverride, TimeScalePlugin};
+use bevy::prelude::*;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+/// Staggers the entities playing animations `T` whose `Poise` breaks
+pub struct PoisePlugin<T>(PhantomData<T>);
+
+impl<T> Default for PoisePlugin<T> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T> Plugin for PoisePlugin<T>
+where
+    T: Default + Eq + Copy + Hash + AnimationLookup<T> + Send + Sync + 'static,
+{
+    fn build(&self, app: &mut App) {
+        // Shared by the poise plugins of every animation enum
+        if !app.is_plugin_added::<TimeScalePlugin>() {
+            app.add_plugin(TimeScalePlugin);
+        }
+        if !app.is_plugin_added::<PoiseRegenerationPlugin>() {
+            app.add_plugin(PoiseRegenerationPlugin);
+        }
+
+        app.add_system(poise_damage_system::<T>.after(DamageStage::Apply))
+            .add_system(hyper_armor_system::<T>.after(AnimationSet));
+    }
+}
+
+struct PoiseRegenerationPlugin;
+
+impl Plugin for PoiseRegenerationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(regenerate_poise_system);
+    }
+}
+
+/// How much punishment an entity takes before flinching
+///
+/// Hits take their poise damage off it, and it breaks when it runs out: the entity is staggered
+/// and its poise is restored. It regenerates once the entity hasn't been hit for a while.
+#[derive(Component, Debug, Clone)]
+pub struct Poise {
+    pub current: f32,
+    pub max: f32,
+    /// Poise regenerated per second
+    pub regen_rate: f32,
+    /// How long after a hit it starts regenerating
+    pub regen_delay: Duration,
+    /// Time left before regenerating
+    regen_cooldown: Duration,
+}
+
+impl Poise {
+    /// Full poise, with the default regeneration
+    #[must_use]
+    pub fn new(max: f32) -> Self {
+        Self {
+            current: max,
+            max,
+            regen_rate: 20.0,
+            regen_delay: Duration::from_secs(2),
+            regen_cooldown: Duration::ZERO,
+        }
+    }
+
+    /// Take `amount` off the poise and restart the regeneration delay, returning whether it
+    /// broke. A broken poise is restored right away.
+    pub fn damage(&mut self, amount: f32) -> bool {
+        self.regen_cooldown = self.regen_delay;
+        self.current -= amount;
+        let broken = self.current <= 0.0;
+        if broken {
+            self.current = self.max;
+        }
+        broken
+    }
+
+    /// Advance the regeneration by `delta`
+    pub fn regenerate(&mut self, delta: Duration) {
+        let regen_time = delta.saturating_sub(self.regen_cooldown);
+        self.regen_cooldown = self.regen_cooldown.saturating_sub(delta);
+        self.current = (self.current + self.regen_rate * regen_time.as_secs_f32()).min(self.max);
+    }
+}
+
+/// The animation an entity plays when its poise breaks
+///
+/// It cuts whatever the entity was doing, even a locked attack, unless that attack is playing a
+/// frame tagged `hyper_armor`.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Stagger<T> {
+    pub animation: T,
+}
+
+/// Takes the poise damage of the hits that landed, and staggers the entities whose poise broke
+pub fn poise_damage_system<T>(
+    mut applied_events: EventReader<DamageApplied>,
+    mut query: Query<(&mut Poise, &Stagger<T>, &mut AnimationSpriteSheet<T>)>,
+) where
+    T: Default + Eq + Copy + Hash + AnimationLookup<T> + Send + Sync + 'static,
+{
+    for event in applied_events.iter() {
+        let Ok((mut poise, stagger, mut sprite_sheet)) = query.get_mut(event.target) else {
+            continue;
+        };
+        // Heavy attacks shrug off the hits landing during their wind-up and swing
+        if sprite_sheet.is_in_tagged_frame("hyper_armor") {
+            continue;
+        }
+        if poise.damage(event.poise_damage) {
+            sprite_sheet.force_animation(stagger.animation);
+        }
+    }
+}
+
+/// Makes the entities playing a frame tagged `hyper_armor` immovable, so heavy attacks aren't
+/// knocked off course
+pub fn hyper_armor_system<T>(mut query: Query<(&mut Velocity, &AnimationSpriteSheet<T>)>)
+where
+    T: Default + Eq + Copy + Hash + AnimationLookup<T> + Send + Sync + 'static,
+{
+    for (mut velocity, sprite_sheet) in query.iter_mut() {
+        let immovable = sprite_sheet.is_in_tagged_frame("hyper_armor");
+        if velocity.immovable != immovable {
+            velocity.immovable = immovable;
+        }
+    }
+}
+
+pub fn regenerate_poise_system(
+    time: ScaledTime,
+    mut query: Query<(&mut Poise, Option<&TimeScaleOverride>)>,
+) {
+    for (mut poise, time_scale_override) in query.iter_mut() {
+        if poise.current < poise.max {
+            poise.regenerate(time.delta(time_scale_override));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn breaks_and_recovers() {
+        let mut poise = Poise::new(30.0);
+        assert!(!poise.damage(20.0));
+        assert_eq!(poise.current, 10.0);
+
+        // Still within the delay
+        poise.regenerate(Duration::from_secs(1));
+        assert_eq!(poise.current, 10.0);
+        poise.regenerate(Duration::from_millis(1500));
+        assert_eq!(poise.current, 20.0);
+
+        assert!(poise.damage(25.0));
+        assert_eq!(poise.current, 30.0);
+    }
+}
@@ -0,0 +1,452 @@
+use crate::animation::machine::AnimationStateMachine;
+use crate::animation::sprite::AnimationSpriteSheet;
+use crate::collision::velocity::Velocity;
+use crate::damage::Invulnerable;
+use crate::health::Health;
+use crate::player::animation::{player_animation_transitions, PlayerAnimation};
+use crate::player::Player;
+use crate::poise::Poise;
+use crate::stamina::Stamina;
+use crate::tilemap::tiled::{Bonfire, PlayerSpawn};
+use crate::util::iso::WorldPosition;
+use bevy::prelude::*;
+use std::time::Duration;
+
+/// How close to a bonfire the player must stand to rest at it
+const REST_DISTANCE: f32 = 80.0;
+/// Time between the player's death and the "YOU DIED" screen starting to fade in
+const DEATH_SCREEN_DELAY: Duration = Duration::from_millis(800);
+const DEATH_SCREEN_FADE: Duration = Duration::from_millis(1500);
+/// Time between the player's death and its respawn
+const RESPAWN_DELAY: Duration = Duration::from_millis(4500);
+
+/// Plays the player's death, respawns it at the last bonfire it rested at, and lets it rest at
+/// bonfires
+pub struct RespawnPlugin;
+
+impl Plugin for RespawnPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PlayerDied>()
+            .add_event::<PlayerRespawned>()
+            .add_event::<Rested>()
+            .init_resource::<Checkpoint>()
+            .add_systems(
+                (
+                    player_death_system,
+                    death_screen_system,
+                    respawn_player_system,
+                    rest_at_bonfire_system,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Sent when the player's health runs out
+#[derive(Debug, Clone)]
+pub struct PlayerDied {
+    pub player: Entity,
+    /// Where the player died, in world space
+    pub position: Vec2,
+}
+
+/// Sent when the player is brought back after dying
+#[derive(Debug, Clone)]
+pub struct PlayerRespawned {
+    pub player: Entity,
+    pub position: Vec2,
+}
+
+/// Sent when the player rests at a bonfire, or respawns at one, which brings the enemies back
+#[derive(Debug, Clone)]
+pub struct Rested {
+    pub player: Entity,
+}
+
+/// Where the player respawns: where it last rested at a bonfire, or else the map's player spawn
+#[derive(Resource, Debug, Default)]
+pub struct Checkpoint {
+    pub position: Option<Vec2>,
+}
+
+/// The player is dead, and respawns once `elapsed` reaches the respawn delay
+#[derive(Component, Debug, Default)]
+pub struct Dead {
+    pub elapsed: Duration,
+}
+
+/// The "YOU DIED" screen
+#[derive(Component)]
+pub struct DeathScreen;
+
+/// Starts the death sequence of the player when its health runs out
+///
+/// The player is made invulnerable so nothing cuts its death clip, and loses its state machine
+/// so the clip holds its last frame until the respawn.
+#[allow(clippy::type_complexity)]
+pub fn player_death_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut died_events: EventWriter<PlayerDied>,
+    mut query: Query<
+        (
+            Entity,
+            &Health,
+            &WorldPosition,
+            &mut AnimationSpriteSheet<PlayerAnimation>,
+        ),
+        (With<Player>, Without<Dead>),
+    >,
+) {
+    for (entity, health, world_position, mut sprite_sheet) in query.iter_mut() {
+        if health.current > 0 {
+            continue;
+        }
+        println!("Player has died.");
+        sprite_sheet.force_animation(PlayerAnimation::Death);
+        commands
+            .entity(entity)
+            .insert((Dead::default(), Invulnerable::new(Duration::MAX)))
+            .remove::<AnimationStateMachine<PlayerAnimation>>();
+        died_events.send(PlayerDied {
+            player: entity,
+            position: world_position.0,
+        });
+
+        // Spawned transparent, faded in by `death_screen_system`
+        commands
+            .spawn((
+                NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                        position_type: PositionType::Absolute,
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: Color::NONE.into(),
+                    ..default()
+                },
+                DeathScreen,
+            ))
+            .with_children(|parent| {
+                parent.spawn(TextBundle::from_section(
+                    "YOU DIED",
+                    TextStyle {
+                        font: asset_server.load("fonts/DejaVuSerif.ttf"),
+                        font_size: 96.0,
+                        color: Color::NONE,
+                    },
+                ));
+            });
+    }
+}
+
+/// Advances the death sequences, fading the "YOU DIED" screen in
+pub fn death_screen_system(
+    time: Res<Time>,
+    mut dead_query: Query<&mut Dead>,
+    mut screen_query: Query<(&mut BackgroundColor, &Children), With<DeathScreen>>,
+    mut text_query: Query<&mut Text>,
+) {
+    for mut dead in dead_query.iter_mut() {
+        dead.elapsed += time.delta();
+        let fade = dead
+            .elapsed
+            .saturating_sub(DEATH_SCREEN_DELAY)
+            .as_secs_f32()
+            / DEATH_SCREEN_FADE.as_secs_f32();
+        let alpha = fade.clamp(0.0, 1.0);
+
+        for (mut background_color, children) in screen_query.iter_mut() {
+            background_color.0 = Color::rgba(0.0, 0.0, 0.0, alpha * 0.6);
+            for child in children.iter() {
+                if let Ok(mut text) = text_query.get_mut(*child) {
+                    for section in text.sections.iter_mut() {
+                        section.style.color = Color::rgba(0.6, 0.05, 0.05, alpha);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Brings the player back once the death sequence is over, at the checkpoint and restored, and
+/// clears the "YOU DIED" screen
+#[allow(clippy::type_complexity)]
+pub fn respawn_player_system(
+    mut commands: Commands,
+    checkpoint: Res<Checkpoint>,
+    spawn_query: Query<&WorldPosition, (With<PlayerSpawn>, Without<Player>)>,
+    screen_query: Query<Entity, With<DeathScreen>>,
+    mut respawned_events: EventWriter<PlayerRespawned>,
+    mut rested_events: EventWriter<Rested>,
+    mut query: Query<
+        (
+            Entity,
+            &Dead,
+            &mut WorldPosition,
+            &mut AnimationSpriteSheet<PlayerAnimation>,
+            &mut Health,
+            Option<&mut Stamina>,
+            Option<&mut Poise>,
+            Option<&mut Velocity>,
+        ),
+        With<Player>,
+    >,
+) {
+    for (
+        entity,
+        dead,
+        mut world_position,
+        mut sprite_sheet,
+        mut health,
+        stamina,
+        poise,
+        velocity,
+    ) in query.iter_mut()
+    {
+        if dead.elapsed < RESPAWN_DELAY {
+            continue;
+        }
+
+        let position = checkpoint
+            .position
+            .or_else(|| spawn_query.iter().next().map(|spawn| spawn.0))
+            .unwrap_or(world_position.0);
+        world_position.0 = position;
+        restore(&mut health, stamina, poise);
+        if let Some(mut velocity) = velocity {
+            velocity.linear = Vec2::ZERO;
+        }
+        sprite_sheet.force_animation(PlayerAnimation::Idle);
+        commands
+            .entity(entity)
+            .remove::<(Dead, Invulnerable)>()
+            .insert(AnimationStateMachine::new(player_animation_transitions()));
+        for screen in screen_query.iter() {
+            commands.entity(screen).despawn_recursive();
+        }
+
+        respawned_events.send(PlayerRespawned {
+            player: entity,
+            position,
+        });
+        // Coming back at a bonfire is resting at it
+        rested_events.send(Rested { player: entity });
+    }
+}
+
+/// Rests at the bonfire the player stands by when E is pressed, restoring the player and making
+/// the bonfire its checkpoint
+#[allow(clippy::type_complexity)]
+pub fn rest_at_bonfire_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut checkpoint: ResMut<Checkpoint>,
+    mut rested_events: EventWriter<Rested>,
+    bonfire_query: Query<&WorldPosition, (With<Bonfire>, Without<Player>)>,
+    mut player_query: Query<
+        (
+            Entity,
+            &WorldPosition,
+            &mut Health,
+            Option<&mut Stamina>,
+            Option<&mut Poise>,
+        ),
+        (With<Player>, Without<Dead>),
+    >,
+) {
+    if !keyboard_input.just_pressed(KeyCode::E) {
+        return;
+    }
+
+    for (entity, world_position, mut health, stamina, poise) in player_query.iter_mut() {
+        let near_bonfire = bonfire_query
+            .iter()
+            .any(|bonfire| bonfire.0.distance(world_position.0) <= REST_DISTANCE);
+        if !near_bonfire {
+            continue;
+        }
+
+        // Where the player stands rather than the bonfire itself, which is solid
+        checkpoint.position = Some(world_position.0);
+        restore(&mut health, stamina, poise);
+        rested_events.send(Rested { player: entity });
+    }
+}
+
+/// Refills the player's health, stamina and poise
+fn restore(health: &mut Health, stamina: Option<Mut<Stamina>>, poise: Option<Mut<Poise>>) {
+    health.current = health.max;
+    if let Some(mut stamina) = stamina {
+        stamina.current = stamina.max;
+    }
+    if let Some(mut poise) = poise {
+        poise.current = poise.max;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enemy::{reset_enemies_system, Enemy};
+    use crate::player::animation::DirectionAtlasHandles;
+    use crate::tilemap::tiled::EnemySpawn;
+    use crate::util::Direction;
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin::default())
+            .init_resource::<Input<KeyCode>>()
+            .add_plugin(RespawnPlugin);
+        app
+    }
+
+    fn spawn_player(app: &mut App, position: Vec2) -> Entity {
+        app.world
+            .spawn((
+                Player,
+                WorldPosition(position),
+                AnimationSpriteSheet::<PlayerAnimation>::default(),
+                AnimationStateMachine::new(player_animation_transitions()),
+                Health {
+                    current: 100,
+                    max: 100,
+                },
+                Stamina::new(100.0),
+                Poise::new(30.0),
+            ))
+            .id()
+    }
+
+    fn sent<E: Clone + Send + Sync + 'static>(app: &App) -> Vec<E> {
+        app.world
+            .resource::<Events<E>>()
+            .iter_current_update_events()
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn dying_keeps_the_player_around() {
+        let mut app = app();
+        let player = spawn_player(&mut app, Vec2::new(40.0, 0.0));
+        app.update();
+        assert!(sent::<PlayerDied>(&app).is_empty());
+
+        app.world.get_mut::<Health>(player).unwrap().current = 0;
+        app.update();
+        let died = sent::<PlayerDied>(&app);
+        assert_eq!(died.len(), 1);
+        assert_eq!(died[0].player, player);
+        assert_eq!(died[0].position, Vec2::new(40.0, 0.0));
+
+        let entity = app.world.entity(player);
+        assert!(entity.contains::<Dead>());
+        assert!(entity.contains::<Invulnerable>());
+        assert!(!entity.contains::<AnimationStateMachine<PlayerAnimation>>());
+        let sprite_sheet = entity
+            .get::<AnimationSpriteSheet<PlayerAnimation>>()
+            .unwrap();
+        assert_eq!(sprite_sheet.current_animation, PlayerAnimation::Death);
+
+        // Still dead, and dying only once
+        app.update();
+        assert!(app.world.entity(player).contains::<Dead>());
+        assert!(sent::<PlayerDied>(&app).is_empty());
+    }
+
+    #[test]
+    fn respawns_restored_at_the_checkpoint() {
+        let mut app = app();
+        let checkpoint = Vec2::new(-120.0, 64.0);
+        app.world.resource_mut::<Checkpoint>().position = Some(checkpoint);
+        let player = spawn_player(&mut app, Vec2::new(300.0, 0.0));
+        app.world.get_mut::<Stamina>(player).unwrap().current = 5.0;
+        app.world.get_mut::<Poise>(player).unwrap().current = 1.0;
+        app.world.get_mut::<Health>(player).unwrap().current = 0;
+        app.update();
+        assert!(sent::<PlayerRespawned>(&app).is_empty());
+
+        app.world.get_mut::<Dead>(player).unwrap().elapsed = RESPAWN_DELAY;
+        app.update();
+        let respawned = sent::<PlayerRespawned>(&app);
+        assert_eq!(respawned.len(), 1);
+        assert_eq!(respawned[0].position, checkpoint);
+        assert_eq!(sent::<Rested>(&app).len(), 1);
+
+        let entity = app.world.entity(player);
+        assert_eq!(entity.get::<WorldPosition>().unwrap().0, checkpoint);
+        assert_eq!(entity.get::<Health>().unwrap().current, 100);
+        assert_eq!(entity.get::<Stamina>().unwrap().current, 100.0);
+        assert_eq!(entity.get::<Poise>().unwrap().current, 30.0);
+        assert!(!entity.contains::<Dead>());
+        assert!(!entity.contains::<Invulnerable>());
+        assert!(entity.contains::<AnimationStateMachine<PlayerAnimation>>());
+        let mut screens = app.world.query_filtered::<(), With<DeathScreen>>();
+        assert_eq!(screens.iter(&app.world).count(), 0);
+    }
+
+    #[test]
+    fn resting_sets_the_checkpoint_and_resets_enemies() {
+        let mut app = app();
+        let mut direction_atlas_handles = DirectionAtlasHandles::default();
+        direction_atlas_handles
+            .0
+            .insert(Direction::SouthWest, Handle::default());
+        app.insert_resource(direction_atlas_handles)
+            .add_system(reset_enemies_system.after(rest_at_bonfire_system));
+
+        let bonfire = Vec2::new(200.0, 0.0);
+        app.world.spawn((Bonfire, WorldPosition(bonfire)));
+        let spawn = app
+            .world
+            .spawn((EnemySpawn, WorldPosition(Vec2::new(500.0, 0.0))))
+            .id();
+        let enemy = app
+            .world
+            .spawn((
+                Enemy { spawn },
+                WorldPosition(Vec2::new(320.0, 40.0)),
+                Health {
+                    current: 10,
+                    max: 50,
+                },
+            ))
+            .id();
+        let player = spawn_player(&mut app, Vec2::ZERO);
+        app.world.get_mut::<Health>(player).unwrap().current = 20;
+
+        let rest = |app: &mut App| {
+            let mut keyboard_input = app.world.resource_mut::<Input<KeyCode>>();
+            keyboard_input.reset_all();
+            keyboard_input.press(KeyCode::E);
+            app.update();
+        };
+
+        // Too far from the bonfire
+        rest(&mut app);
+        assert!(sent::<Rested>(&app).is_empty());
+        assert_eq!(app.world.resource::<Checkpoint>().position, None);
+
+        let by_the_bonfire = Vec2::new(150.0, 0.0);
+        app.world.get_mut::<WorldPosition>(player).unwrap().0 = by_the_bonfire;
+        rest(&mut app);
+        assert_eq!(sent::<Rested>(&app).len(), 1);
+        assert_eq!(
+            app.world.resource::<Checkpoint>().position,
+            Some(by_the_bonfire)
+        );
+        assert_eq!(app.world.get::<Health>(player).unwrap().current, 100);
+
+        // The damaged enemy is replaced by a fresh one at its spawn
+        assert!(app.world.get_entity(enemy).is_none());
+        let mut enemies = app.world.query::<(&Enemy, &WorldPosition, &Health)>();
+        let enemies: Vec<_> = enemies
+            .iter(&app.world)
+            .map(|(enemy, position, health)| (enemy.spawn, position.0, health.current))
+            .collect();
+        assert_eq!(enemies, vec![(spawn, Vec2::new(500.0, 0.0), 50)]);
+    }
+}
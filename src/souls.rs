@@ -0,0 +1,215 @@
+use crate::damage::{DamageApplied, DamageStage};
+use crate::player::Player;
+use crate::respawn::{Dead, PlayerDied};
+use crate::util::iso::WorldPosition;
+use crate::ysort::YSort;
+use bevy::prelude::*;
+
+/// How close the player must get to its bloodstain to recover its souls
+const PICKUP_DISTANCE: f32 = 32.0;
+
+/// Gives souls for kills, and drops the player's souls where it dies
+pub struct SoulsPlugin;
+
+impl Plugin for SoulsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(soul_reward_system.after(DamageStage::Apply))
+            .add_systems((drop_souls_system, recover_souls_system).chain());
+    }
+}
+
+/// The souls an entity carries, spent on upgrades and lost on death
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Souls(pub u32);
+
+/// Souls given to whoever kills the entity
+#[derive(Component, Debug, Clone, Copy)]
+pub struct SoulReward(pub u32);
+
+/// The souls the player dropped where it died
+///
+/// Touching it gives them back. Dying again before that drops a new one, and the souls of this
+/// one are gone for good.
+#[derive(Component, Debug)]
+pub struct Bloodstain {
+    pub souls: u32,
+}
+
+/// Gives the killers their victims' soul reward
+pub fn soul_reward_system(
+    mut applied_events: EventReader<DamageApplied>,
+    reward_query: Query<&SoulReward>,
+    mut souls_query: Query<&mut Souls>,
+) {
+    for event in applied_events.iter() {
+        if !event.is_lethal {
+            continue;
+        }
+        let Ok(reward) = reward_query.get(event.target) else {
+            continue;
+        };
+        if let Some(mut souls) = event
+            .source
+            .and_then(|source| souls_query.get_mut(source).ok())
+        {
+            souls.0 += reward.0;
+        }
+    }
+}
+
+/// Drops the souls of the player where it died, replacing the bloodstain of its previous death
+pub fn drop_souls_system(
+    mut commands: Commands,
+    mut died_events: EventReader<PlayerDied>,
+    mut souls_query: Query<&mut Souls>,
+    bloodstain_query: Query<Entity, With<Bloodstain>>,
+) {
+    for event in died_events.iter() {
+        for bloodstain in bloodstain_query.iter() {
+            commands.entity(bloodstain).despawn_recursive();
+        }
+
+        let Ok(mut souls) = souls_query.get_mut(event.player) else {
+            continue;
+        };
+        let dropped = std::mem::take(&mut souls.0);
+        if dropped == 0 {
+            continue;
+        }
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba(0.8, 0.05, 0.1, 0.8),
+                    custom_size: Some(Vec2::new(20.0, 10.0)),
+                    ..default()
+                },
+                ..default()
+            },
+            WorldPosition(event.position),
+            YSort::default(),
+            Bloodstain { souls: dropped },
+        ));
+    }
+}
+
+/// Gives the souls of the bloodstains the living player touches back
+#[allow(clippy::type_complexity)]
+pub fn recover_souls_system(
+    mut commands: Commands,
+    bloodstain_query: Query<(Entity, &Bloodstain, &WorldPosition)>,
+    mut player_query: Query<(&mut Souls, &WorldPosition), (With<Player>, Without<Dead>)>,
+) {
+    for (mut souls, player_position) in player_query.iter_mut() {
+        for (entity, bloodstain, position) in bloodstain_query.iter() {
+            if position.0.distance(player_position.0) > PICKUP_DISTANCE {
+                continue;
+            }
+            souls.0 += bloodstain.souls;
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn die(app: &mut App, player: Entity, position: Vec2) {
+        app.world.send_event(PlayerDied { player, position });
+        app.update();
+    }
+
+    fn bloodstains(app: &mut App) -> Vec<(u32, Vec2)> {
+        app.world
+            .query::<(&Bloodstain, &WorldPosition)>()
+            .iter(&app.world)
+            .map(|(bloodstain, position)| (bloodstain.souls, position.0))
+            .collect()
+    }
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_event::<DamageApplied>()
+            .add_event::<PlayerDied>()
+            .add_plugin(SoulsPlugin);
+        app
+    }
+
+    fn spawn_dead_player(app: &mut App, souls: u32) -> Entity {
+        app.world
+            .spawn((
+                Player,
+                Souls(souls),
+                WorldPosition(Vec2::ZERO),
+                Dead::default(),
+            ))
+            .id()
+    }
+
+    #[test]
+    fn dying_twice_loses_the_dropped_souls() {
+        let mut app = app();
+        let player = spawn_dead_player(&mut app, 300);
+
+        let grave = Vec2::new(500.0, 0.0);
+        die(&mut app, player, grave);
+        assert_eq!(bloodstains(&mut app), vec![(300, grave)]);
+        assert_eq!(app.world.get::<Souls>(player), Some(&Souls(0)));
+
+        // Killed again on the way back, after earning a few more
+        app.world.get_mut::<Souls>(player).unwrap().0 = 40;
+        die(&mut app, player, Vec2::new(200.0, 0.0));
+        assert_eq!(bloodstains(&mut app), vec![(40, Vec2::new(200.0, 0.0))]);
+
+        // Respawned, then touching it
+        app.world.entity_mut(player).remove::<Dead>();
+        app.world.get_mut::<WorldPosition>(player).unwrap().0 = Vec2::new(210.0, 0.0);
+        app.update();
+        assert!(bloodstains(&mut app).is_empty());
+        assert_eq!(app.world.get::<Souls>(player), Some(&Souls(40)));
+    }
+
+    #[test]
+    fn dying_again_despawns_the_previous_bloodstain() {
+        let mut app = app();
+        let player = spawn_dead_player(&mut app, 120);
+        die(&mut app, player, Vec2::new(500.0, 0.0));
+        let mut query = app.world.query_filtered::<Entity, With<Bloodstain>>();
+        let first = query.single(&app.world);
+
+        // Nothing earned since, so nothing dropped
+        die(&mut app, player, Vec2::new(100.0, 0.0));
+        assert!(app.world.get_entity(first).is_none());
+        assert!(bloodstains(&mut app).is_empty());
+
+        // Respawned and back at the first grave, its souls are gone for good
+        app.world.entity_mut(player).remove::<Dead>();
+        app.world.get_mut::<WorldPosition>(player).unwrap().0 = Vec2::new(500.0, 0.0);
+        app.update();
+        assert_eq!(app.world.get::<Souls>(player), Some(&Souls(0)));
+    }
+
+    #[test]
+    fn touching_the_bloodstain_recovers_its_souls() {
+        let mut app = app();
+        let player = spawn_dead_player(&mut app, 250);
+        let grave = Vec2::new(300.0, 100.0);
+        die(&mut app, player, grave);
+
+        // Respawned far away, having earned a few more
+        app.world.entity_mut(player).remove::<Dead>();
+        app.world.get_mut::<Souls>(player).unwrap().0 = 30;
+        app.update();
+        assert_eq!(bloodstains(&mut app), vec![(250, grave)]);
+
+        app.world.get_mut::<WorldPosition>(player).unwrap().0 =
+            grave - Vec2::new(PICKUP_DISTANCE, 0.0);
+        app.update();
+        assert!(bloodstains(&mut app).is_empty());
+        assert_eq!(app.world.get::<Souls>(player), Some(&Souls(280)));
+
+        // Recovered only once
+        app.update();
+        assert_eq!(app.world.get::<Souls>(player), Some(&Souls(280)));
+    }
+}
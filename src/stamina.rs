@@ -0,0 +1,159 @@
+use crate::animation::events::AnimationStarted;
+use crate::time_scale::{ScaledTime, TimeScaleOverride, TimeScalePlugin};
+use bevy::prelude::*;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::Duration;
+
+pub struct StaminaPlugin;
+
+impl Plugin for StaminaPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<TimeScalePlugin>() {
+            app.add_plugin(TimeScalePlugin);
+        }
+
+        app.add_system(regenerate_stamina_system);
+    }
+}
+
+/// What actions are paid with
+///
+/// An entity can act as long as it has some stamina left, and the action may take it below
+/// zero. It's then exhausted, and waits longer before regenerating.
+#[derive(Component, Debug, Clone)]
+pub struct Stamina {
+    pub current: f32,
+    pub max: f32,
+    /// Stamina regenerated per second
+    pub regen_rate: f32,
+    /// How long after spending stamina it starts regenerating
+    pub regen_delay: Duration,
+    /// The regeneration delay once exhausted
+    pub exhausted_regen_delay: Duration,
+    /// Time left before regenerating
+    regen_cooldown: Duration,
+}
+
+impl Stamina {
+    /// Full stamina, with the default regeneration
+    #[must_use]
+    pub fn new(max: f32) -> Self {
+        Self {
+            current: max,
+            max,
+            regen_rate: 45.0,
+            regen_delay: Duration::from_millis(600),
+            exhausted_regen_delay: Duration::from_millis(1500),
+            regen_cooldown: Duration::ZERO,
+        }
+    }
+
+    /// Whether there's stamina left to start an action
+    #[must_use]
+    pub fn can_act(&self) -> bool {
+        self.current > 0.0
+    }
+
+    /// Whether an action took the stamina below zero
+    #[must_use]
+    pub fn is_exhausted(&self) -> bool {
+        self.current < 0.0
+    }
+
+    /// Spend stamina, going below zero if there isn't enough, and restart the regeneration delay
+    ///
+    /// Going no lower than `-max`, so a single action can't exhaust for too long.
+    pub fn spend(&mut self, cost: f32) {
+        self.current = (self.current - cost).max(-self.max);
+        self.regen_cooldown = if self.is_exhausted() {
+            self.exhausted_regen_delay
+        } else {
+            self.regen_delay
+        };
+    }
+
+    /// Advance the regeneration by `delta`
+    pub fn regenerate(&mut self, delta: Duration) {
+        let regen_time = delta.saturating_sub(self.regen_cooldown);
+        self.regen_cooldown = self.regen_cooldown.saturating_sub(delta);
+        self.current = (self.current + self.regen_rate * regen_time.as_secs_f32()).min(self.max);
+    }
+}
+
+/// What the actions of an entity playing animations `T` cost
+#[derive(Component, Debug, Clone)]
+pub struct StaminaCosts<T> {
+    /// Cost of playing each animation, paid when it starts
+    pub actions: HashMap<T, f32>,
+    /// Cost of sprinting for a second
+    pub sprint: f32,
+}
+
+pub fn regenerate_stamina_system(
+    time: ScaledTime,
+    mut query: Query<(&mut Stamina, Option<&TimeScaleOverride>)>,
+) {
+    for (mut stamina, time_scale_override) in query.iter_mut() {
+        if stamina.current < stamina.max {
+            stamina.regenerate(time.delta(time_scale_override));
+        }
+    }
+}
+
+/// Charges the stamina cost of the actions as their animation starts, so a chained attack
+/// costs what the attack it chained into costs
+pub fn spend_action_stamina_system<T>(
+    mut started_events: EventReader<AnimationStarted<T>>,
+    mut query: Query<(&mut Stamina, &StaminaCosts<T>)>,
+) where
+    T: Eq + Hash + Send + Sync + 'static,
+{
+    for event in started_events.iter() {
+        let Ok((mut stamina, costs)) = query.get_mut(event.entity) else {
+            continue;
+        };
+        if let Some(cost) = costs.actions.get(&event.animation) {
+            stamina.spend(*cost);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    #[test]
+    fn regenerates_after_the_delay() {
+        let mut stamina = Stamina::new(100.0);
+        stamina.spend(40.0);
+        assert_eq!(stamina.current, 60.0);
+
+        stamina.regenerate(Duration::from_millis(500));
+        assert_eq!(stamina.current, 60.0);
+        // 100ms of delay left, then 400ms of regeneration
+        stamina.regenerate(Duration::from_millis(500));
+        assert!((stamina.current - (60.0 + 45.0 * 0.4)).abs() < EPSILON);
+
+        stamina.regenerate(Duration::from_secs(10));
+        assert_eq!(stamina.current, 100.0);
+    }
+
+    #[test]
+    fn overspending_exhausts() {
+        let mut stamina = Stamina::new(100.0);
+        stamina.spend(80.0);
+        assert!(stamina.can_act());
+        stamina.spend(30.0);
+        assert!(stamina.is_exhausted());
+        assert!(!stamina.can_act());
+
+        // The longer delay applies
+        stamina.regenerate(Duration::from_millis(1500));
+        assert_eq!(stamina.current, -10.0);
+        stamina.regenerate(Duration::from_secs(1));
+        assert!(stamina.can_act());
+    }
+}
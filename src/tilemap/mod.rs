@@ -0,0 +1,366 @@
+pub mod tiled;
+
+use crate::util::iso::IsoGrid;
+use crate::ysort::YSort;
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+pub struct TileMapPlugin;
+
+impl Plugin for TileMapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(tiled::TiledMapPlugin)
+            .add_systems((respawn_changed_chunks_system, spawn_visible_chunks_system).chain());
+    }
+}
+
+/// Tiles per side of a chunk, the unit tile maps are spawned and despawned in
+pub const DEFAULT_CHUNK_SIZE: u32 = 16;
+/// How many chunks around the visible ones stay spawned, so tall tiles and fast camera moves
+/// don't pop in at the edge of the screen
+const CHUNK_MARGIN: i32 = 1;
+
+/// The layers of a `TileMap`. The ground and decoration are drawn under everything else, walls
+/// are y-sorted with the characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TileLayer {
+    Ground,
+    Decoration,
+    Walls,
+}
+
+impl TileLayer {
+    pub const ALL: [TileLayer; 3] = [TileLayer::Ground, TileLayer::Decoration, TileLayer::Walls];
+
+    /// Z of the layer's sprites, or `None` if they are y-sorted
+    #[must_use]
+    fn z(self) -> Option<f32> {
+        match self {
+            TileLayer::Ground => Some(-900.0),
+            TileLayer::Decoration => Some(-899.0),
+            TileLayer::Walls => None,
+        }
+    }
+}
+
+/// An isometric map of `size` tiles, each layer holding the atlas index of every tile, if any
+///
+/// The tiles are laid out on the `IsoGrid`, tile (0, 0) at the world origin, so the entity
+/// holding the map should stay at the origin. Only the chunks around the camera are spawned.
+#[derive(Component, Debug, Clone)]
+pub struct TileMap {
+    pub size: UVec2,
+    pub atlas: Handle<TextureAtlas>,
+    /// Size of a cell of the atlas. The tile's diamond is at the bottom of the cell, anything
+    /// above it (walls, pillars) rises above the ground.
+    pub tile_sprite_size: Vec2,
+    pub chunk_size: u32,
+    layers: HashMap<TileLayer, Vec<Option<usize>>>,
+}
+
+impl TileMap {
+    /// An empty map
+    #[must_use]
+    pub fn new(size: UVec2, atlas: Handle<TextureAtlas>, tile_sprite_size: Vec2) -> Self {
+        let tile_count = (size.x * size.y) as usize;
+        Self {
+            size,
+            atlas,
+            tile_sprite_size,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            layers: TileLayer::ALL
+                .into_iter()
+                .map(|layer| (layer, vec![None; tile_count]))
+                .collect(),
+        }
+    }
+
+    #[must_use]
+    pub fn contains(&self, tile: IVec2) -> bool {
+        tile.cmpge(IVec2::ZERO).all() && tile.as_uvec2().cmplt(self.size).all()
+    }
+
+    fn cell(&self, tile: IVec2) -> Option<usize> {
+        self.contains(tile)
+            .then(|| (tile.y as u32 * self.size.x + tile.x as u32) as usize)
+    }
+
+    /// Returns the atlas index of a tile, or `None` if the layer has no tile there
+    #[must_use]
+    pub fn get(&self, layer: TileLayer, tile: IVec2) -> Option<usize> {
+        self.cell(tile).and_then(|cell| self.layers[&layer][cell])
+    }
+
+    /// Returns the atlas index of the tile at a world position
+    #[must_use]
+    pub fn get_at(&self, grid: &IsoGrid, layer: TileLayer, world: Vec2) -> Option<usize> {
+        self.get(layer, grid.world_to_tile(world))
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `tile` is outside the map
+    pub fn set(&mut self, layer: TileLayer, tile: IVec2, atlas_index: Option<usize>) {
+        let cell = self
+            .cell(tile)
+            .unwrap_or_else(|| panic!("tile {tile} outside of a {} map", self.size));
+        self.layers.get_mut(&layer).unwrap()[cell] = atlas_index;
+    }
+
+    /// Set every tile of a layer
+    pub fn fill(&mut self, layer: TileLayer, atlas_index: Option<usize>) {
+        self.layers.get_mut(&layer).unwrap().fill(atlas_index);
+    }
+
+    /// Returns the number of chunks along each axis
+    #[must_use]
+    fn chunk_count(&self) -> IVec2 {
+        ((self.size + self.chunk_size - 1) / self.chunk_size).as_ivec2()
+    }
+
+    /// Returns the first and last chunks to spawn for a camera seeing `view`, in screen space
+    ///
+    /// The range includes the margin, and is empty when the view is off the map.
+    #[must_use]
+    fn visible_chunks(&self, grid: &IsoGrid, view: Rect) -> (IVec2, IVec2) {
+        // The tiles under the corners of the view bound the visible ones
+        let corners = [
+            view.min,
+            view.max,
+            Vec2::new(view.min.x, view.max.y),
+            Vec2::new(view.max.x, view.min.y),
+        ]
+        .map(|corner| grid.screen_to_tile(corner));
+        let min_tile = corners.into_iter().reduce(IVec2::min).unwrap();
+        let max_tile = corners.into_iter().reduce(IVec2::max).unwrap();
+
+        let chunk_size = self.chunk_size as i32;
+        let to_chunk =
+            |tile: IVec2| IVec2::new(tile.x.div_euclid(chunk_size), tile.y.div_euclid(chunk_size));
+        let min_chunk = (to_chunk(min_tile) - CHUNK_MARGIN).max(IVec2::ZERO);
+        let max_chunk = (to_chunk(max_tile) + CHUNK_MARGIN).min(self.chunk_count() - 1);
+        (min_chunk, max_chunk)
+    }
+}
+
+/// The chunks of a `TileMap` currently spawned, as children of its entity
+#[derive(Component, Debug, Default)]
+pub struct TileMapChunks(HashMap<IVec2, Entity>);
+
+#[derive(Bundle)]
+pub struct TileMapBundle {
+    pub tile_map: TileMap,
+    pub chunks: TileMapChunks,
+    pub spatial: SpatialBundle,
+}
+
+impl TileMapBundle {
+    #[must_use]
+    pub fn new(tile_map: TileMap) -> Self {
+        Self {
+            tile_map,
+            chunks: TileMapChunks::default(),
+            spatial: SpatialBundle::default(),
+        }
+    }
+}
+
+/// Despawns the chunks of the maps whose tiles changed, so they are spawned again with the new
+/// tiles
+fn respawn_changed_chunks_system(
+    mut commands: Commands,
+    mut query: Query<&mut TileMapChunks, Changed<TileMap>>,
+) {
+    for mut chunks in query.iter_mut() {
+        for (_, chunk_entity) in chunks.0.drain() {
+            commands.entity(chunk_entity).despawn_recursive();
+        }
+    }
+}
+
+/// Spawns the chunks of every tile map around what the camera sees, and despawns the ones that
+/// went out of view
+fn spawn_visible_chunks_system(
+    mut commands: Commands,
+    grid: Res<IsoGrid>,
+    camera_query: Query<(&Transform, &OrthographicProjection), With<Camera>>,
+    mut map_query: Query<(Entity, &TileMap, &mut TileMapChunks)>,
+) {
+    let Ok((camera_transform, projection)) = camera_query.get_single() else {
+        return;
+    };
+
+    let center = camera_transform.translation.truncate();
+    let view = Rect::from_corners(center + projection.area.min, center + projection.area.max);
+
+    for (map_entity, tile_map, mut chunks) in map_query.iter_mut() {
+        let (min_chunk, max_chunk) = tile_map.visible_chunks(&grid, view);
+        let is_visible =
+            |chunk: IVec2| chunk.cmpge(min_chunk).all() && chunk.cmple(max_chunk).all();
+
+        chunks.0.retain(|chunk, chunk_entity| {
+            let visible = is_visible(*chunk);
+            if !visible {
+                commands.entity(*chunk_entity).despawn_recursive();
+            }
+            visible
+        });
+
+        for y in min_chunk.y..=max_chunk.y {
+            for x in min_chunk.x..=max_chunk.x {
+                let chunk = IVec2::new(x, y);
+                if let Entry::Vacant(entry) = chunks.0.entry(chunk) {
+                    let chunk_entity = spawn_chunk(&mut commands, &grid, tile_map, chunk);
+                    commands.entity(map_entity).add_child(chunk_entity);
+                    entry.insert(chunk_entity);
+                }
+            }
+        }
+    }
+}
+
+/// Spawns an entity holding a sprite for every tile of a chunk, on every layer
+fn spawn_chunk(
+    commands: &mut Commands,
+    grid: &IsoGrid,
+    tile_map: &TileMap,
+    chunk: IVec2,
+) -> Entity {
+    // Scale the atlas cells to the grid, and put the center of their diamond on the tile's
+    let size = tile_map.tile_sprite_size * grid.tile_width / tile_map.tile_sprite_size.x;
+    let anchor = Anchor::Custom(Vec2::new(0.0, grid.tile_size().y / 2.0 / size.y - 0.5));
+
+    let start = chunk * tile_map.chunk_size as i32;
+    let end = (start + tile_map.chunk_size as i32).min(tile_map.size.as_ivec2());
+
+    commands
+        .spawn(SpatialBundle::default())
+        .with_children(|parent| {
+            for layer in TileLayer::ALL {
+                for y in start.y..end.y {
+                    for x in start.x..end.x {
+                        let tile = IVec2::new(x, y);
+                        let Some(index) = tile_map.get(layer, tile) else {
+                            continue;
+                        };
+                        let screen = grid.tile_to_screen(tile);
+                        let mut tile_entity = parent.spawn(SpriteSheetBundle {
+                            texture_atlas: tile_map.atlas.clone(),
+                            sprite: TextureAtlasSprite {
+                                index,
+                                custom_size: Some(size),
+                                anchor: anchor.clone(),
+                                ..default()
+                            },
+                            transform: Transform::from_translation(
+                                screen.extend(layer.z().unwrap_or_default()),
+                            ),
+                            ..default()
+                        });
+                        // Sorted by the center of their diamond, so characters standing on the
+                        // tiles behind are drawn behind them
+                        if layer.z().is_none() {
+                            tile_entity.insert(YSort::default());
+                        }
+                    }
+                }
+            }
+        })
+        .id()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::iso::Projection;
+
+    #[test]
+    fn layers_hold_their_own_tiles() {
+        let mut tile_map = TileMap::new(UVec2::new(4, 3), Handle::default(), Vec2::splat(64.0));
+        tile_map.fill(TileLayer::Ground, Some(1));
+        tile_map.set(TileLayer::Walls, IVec2::new(3, 2), Some(7));
+
+        assert_eq!(tile_map.get(TileLayer::Walls, IVec2::new(3, 2)), Some(7));
+        assert_eq!(tile_map.get(TileLayer::Walls, IVec2::new(2, 2)), None);
+        assert_eq!(tile_map.get(TileLayer::Ground, IVec2::new(3, 2)), Some(1));
+        assert_eq!(tile_map.get(TileLayer::Decoration, IVec2::new(3, 2)), None);
+
+        tile_map.set(TileLayer::Walls, IVec2::new(3, 2), None);
+        assert_eq!(tile_map.get(TileLayer::Walls, IVec2::new(3, 2)), None);
+
+        // Outside the map, even next to a filled tile
+        for tile in [
+            IVec2::new(4, 2),
+            IVec2::new(3, 3),
+            IVec2::new(-1, 0),
+            IVec2::new(0, -1),
+        ] {
+            assert!(!tile_map.contains(tile));
+            assert_eq!(tile_map.get(TileLayer::Ground, tile), None);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "outside of a")]
+    fn setting_outside_the_map_panics() {
+        let mut tile_map = TileMap::new(UVec2::new(4, 3), Handle::default(), Vec2::splat(64.0));
+        tile_map.set(TileLayer::Ground, IVec2::new(0, 3), Some(1));
+    }
+
+    #[test]
+    fn world_positions_round_to_the_nearest_tile() {
+        let grid = IsoGrid::new(Projection::Dimetric, 64.0);
+        let mut tile_map = TileMap::new(UVec2::new(2, 2), Handle::default(), Vec2::splat(64.0));
+        tile_map.set(TileLayer::Ground, IVec2::new(0, 0), Some(1));
+        tile_map.set(TileLayer::Ground, IVec2::new(1, 0), Some(2));
+        tile_map.set(TileLayer::Ground, IVec2::new(1, 1), Some(4));
+        let get_at = |x: f32, y: f32| tile_map.get_at(&grid, TileLayer::Ground, Vec2::new(x, y));
+
+        assert_eq!(get_at(0.0, 0.0), Some(1));
+        // Tiles are centered on their world position, so the edge between two belongs to the
+        // farther one
+        assert_eq!(get_at(31.9, 0.0), Some(1));
+        assert_eq!(get_at(32.0, 0.0), Some(2));
+        assert_eq!(get_at(95.9, 95.9), Some(4));
+
+        assert_eq!(get_at(-31.9, 0.0), Some(1));
+        assert_eq!(get_at(-32.0, 0.0), None);
+        assert_eq!(get_at(96.0, 0.0), None);
+        assert_eq!(get_at(0.0, 1000.0), None);
+    }
+
+    #[test]
+    fn chunks_around_the_view_are_visible() {
+        let grid = IsoGrid::new(Projection::Dimetric, 64.0);
+        let mut tile_map = TileMap::new(UVec2::new(64, 64), Handle::default(), Vec2::splat(64.0));
+        tile_map.chunk_size = 4;
+        let view_around = |tile: IVec2| {
+            let center = grid.tile_to_screen(tile);
+            Rect::from_corners(
+                center - Vec2::new(100.0, 60.0),
+                center + Vec2::new(100.0, 60.0),
+            )
+        };
+
+        // Tiles 29 to 35 along both axes are seen, in chunks 7 and 8
+        assert_eq!(
+            tile_map.visible_chunks(&grid, view_around(IVec2::new(32, 32))),
+            (IVec2::new(6, 6), IVec2::new(9, 9))
+        );
+        // Clamped to the map at its corner
+        assert_eq!(
+            tile_map.visible_chunks(&grid, view_around(IVec2::ZERO)),
+            (IVec2::ZERO, IVec2::ONE)
+        );
+        assert_eq!(
+            tile_map.visible_chunks(&grid, view_around(IVec2::new(63, 63))),
+            (IVec2::new(14, 14), IVec2::new(15, 15))
+        );
+
+        let (min_chunk, max_chunk) =
+            tile_map.visible_chunks(&grid, view_around(IVec2::new(-100, -100)));
+        assert!(max_chunk.cmplt(min_chunk).any());
+    }
+}
@@ -0,0 +1,936 @@
+use super::{TileLayer, TileMap, TileMapBundle};
+use crate::collision::{Collider, StaticBody};
+use crate::player::Player;
+use crate::util::iso::{IsoGrid, Projection, WorldPosition};
+use bevy::asset::{AssetLoader, AssetPath, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{self, Path, PathBuf};
+use std::str::FromStr;
+
+/// Registers the `TiledMap` asset and its loader, and spawns the maps once they are loaded
+pub struct TiledMapPlugin;
+
+impl Plugin for TiledMapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<TiledMap>()
+            .init_asset_loader::<TiledMapLoader>()
+            .add_systems((spawn_tiled_maps_system, move_player_to_spawn_system).chain());
+    }
+}
+
+/// The bits of a tile's global id flagging it as flipped, which the tile map doesn't support
+const GID_FLIP_FLAGS: u32 = 0xF000_0000;
+
+/// A map made in Tiled, loaded from a `.tmx` or `.tmj` file
+///
+/// The map must be isometric, with a single tileset embedded in it and made of one image, and
+/// its tile layers saved as CSV. Tile layers go to the `TileLayer` named by their `layer`
+/// property, or else by their name (`ground`, `decoration` or `walls`). Object layers hold the
+/// markers: objects whose type (or class) is `player_spawn`, `enemy_spawn`, `bonfire` or `door`.
+/// Objects whose `solid` property is set block movement: rectangles, circles and convex polygons.
+#[derive(Debug, Clone, TypeUuid)]
+#[uuid = "0b2e7c4d-8a61-4f3e-b5d2-6c9a1e3f7d48"]
+pub struct TiledMap {
+    /// Size in tiles
+    pub size: UVec2,
+    /// Size of a tile's diamond in pixels
+    pub tile_size: Vec2,
+    pub tileset: TiledTileset,
+    pub tile_layers: Vec<TiledTileLayer>,
+    pub objects: Vec<TiledObject>,
+    pub properties: TiledProperties,
+}
+
+#[derive(Debug, Clone)]
+pub struct TiledTileset {
+    pub first_gid: u32,
+    /// Asset path of the tileset image
+    pub image_path: PathBuf,
+    /// The tileset image, loaded with the map
+    pub image: Handle<Image>,
+    /// Size of a tile of the image
+    pub tile_size: Vec2,
+    pub columns: usize,
+    pub tile_count: usize,
+    pub spacing: f32,
+    pub margin: f32,
+}
+
+impl TiledTileset {
+    /// Returns the atlas index of a tile's global id, or `None` for an empty tile
+    #[must_use]
+    pub fn atlas_index(&self, gid: u32) -> Option<usize> {
+        let gid = gid & !GID_FLIP_FLAGS;
+        (gid != 0).then(|| (gid - self.first_gid) as usize)
+    }
+
+    #[must_use]
+    pub fn atlas(&self) -> TextureAtlas {
+        TextureAtlas::from_grid(
+            self.image.clone(),
+            self.tile_size,
+            self.columns,
+            self.tile_count.div_ceil(self.columns),
+            Some(Vec2::splat(self.spacing)),
+            Some(Vec2::splat(self.margin)),
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TiledTileLayer {
+    pub name: String,
+    pub layer: TileLayer,
+    /// Global id of every tile, row by row in Tiled's tile coordinates, 0 for no tile
+    pub gids: Vec<u32>,
+    pub properties: TiledProperties,
+}
+
+#[derive(Debug, Clone)]
+pub struct TiledObject {
+    pub name: String,
+    /// The object's type, or class since Tiled 1.9
+    pub kind: String,
+    /// Center of the object in Tiled's pixel coordinates, where a tile is `tile_size.y` pixels
+    /// along both axes
+    pub position: Vec2,
+    /// Shape of the object in world space, if it's solid
+    pub collider: Option<Collider>,
+    pub properties: TiledProperties,
+}
+
+/// The shape of an object, its vertices and size in Tiled's pixel coordinates
+enum TiledShape {
+    Point,
+    Rectangle(Vec2),
+    Ellipse(Vec2),
+    Polygon(Vec<Vec2>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TiledProperty {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    /// Strings, and the other property types (colors, files, objects) as written in the file
+    String(String),
+}
+
+pub type TiledProperties = HashMap<String, TiledProperty>;
+
+impl TiledMap {
+    /// Parse an XML `.tmx` map, resolving the tileset image relative to `directory`
+    pub fn from_tmx(bytes: &[u8], directory: &Path) -> Result<Self, TiledMapError> {
+        let text =
+            std::str::from_utf8(bytes).map_err(|error| TiledMapError::Syntax(error.to_string()))?;
+        let document = roxmltree::Document::parse(text)
+            .map_err(|error| TiledMapError::Syntax(error.to_string()))?;
+        let map = document.root_element();
+        if !map.has_tag_name("map") {
+            return Err(TiledMapError::Syntax(
+                "expected a <map> element".to_string(),
+            ));
+        }
+        if map.attribute("infinite") == Some("1") {
+            return Err(TiledMapError::Unsupported("infinite maps".to_string()));
+        }
+
+        let mut tilesets = map.children().filter(|node| node.has_tag_name("tileset"));
+        let (Some(tileset), None) = (tilesets.next(), tilesets.next()) else {
+            return Err(TiledMapError::Unsupported(
+                "maps without exactly one tileset".to_string(),
+            ));
+        };
+        if tileset.attribute("source").is_some() {
+            return Err(TiledMapError::Unsupported(
+                "external tilesets, embed the tileset in the map".to_string(),
+            ));
+        }
+        let Some(image) = tileset.children().find(|node| node.has_tag_name("image")) else {
+            return Err(TiledMapError::Unsupported(
+                "tilesets made of separate images".to_string(),
+            ));
+        };
+
+        let mut tiled_map = Self {
+            size: UVec2::new(xml_attribute(map, "width")?, xml_attribute(map, "height")?),
+            tile_size: Vec2::new(
+                xml_attribute(map, "tilewidth")?,
+                xml_attribute(map, "tileheight")?,
+            ),
+            tileset: TiledTileset {
+                first_gid: xml_attribute(tileset, "firstgid")?,
+                image_path: resolve_path(directory, &xml_attribute::<String>(image, "source")?),
+                image: Handle::default(),
+                tile_size: Vec2::new(
+                    xml_attribute(tileset, "tilewidth")?,
+                    xml_attribute(tileset, "tileheight")?,
+                ),
+                columns: xml_attribute(tileset, "columns")?,
+                tile_count: xml_attribute(tileset, "tilecount")?,
+                spacing: xml_optional_attribute(tileset, "spacing")?.unwrap_or_default(),
+                margin: xml_optional_attribute(tileset, "margin")?.unwrap_or_default(),
+            },
+            tile_layers: Vec::new(),
+            objects: Vec::new(),
+            properties: xml_properties(map)?,
+        };
+        tiled_map.check_orientation(&xml_attribute::<String>(map, "orientation")?)?;
+        tiled_map.read_xml_layers(map)?;
+        tiled_map.validate()?;
+        Ok(tiled_map)
+    }
+
+    /// Parse a JSON `.tmj` map, resolving the tileset image relative to `directory`
+    pub fn from_tmj(bytes: &[u8], directory: &Path) -> Result<Self, TiledMapError> {
+        let map: Value = serde_json::from_slice(bytes)
+            .map_err(|error| TiledMapError::Syntax(error.to_string()))?;
+        if map["infinite"] == Value::Bool(true) {
+            return Err(TiledMapError::Unsupported("infinite maps".to_string()));
+        }
+
+        let tileset = match json_field::<Vec<Value>>(&map, "tilesets")?.as_slice() {
+            [tileset] => tileset.clone(),
+            _ => {
+                return Err(TiledMapError::Unsupported(
+                    "maps without exactly one tileset".to_string(),
+                ))
+            }
+        };
+        if tileset.get("source").is_some() {
+            return Err(TiledMapError::Unsupported(
+                "external tilesets, embed the tileset in the map".to_string(),
+            ));
+        }
+        if tileset.get("image").is_none() {
+            return Err(TiledMapError::Unsupported(
+                "tilesets made of separate images".to_string(),
+            ));
+        }
+
+        let mut tiled_map = Self {
+            size: UVec2::new(json_field(&map, "width")?, json_field(&map, "height")?),
+            tile_size: Vec2::new(
+                json_field(&map, "tilewidth")?,
+                json_field(&map, "tileheight")?,
+            ),
+            tileset: TiledTileset {
+                first_gid: json_field(&tileset, "firstgid")?,
+                image_path: resolve_path(directory, &json_field::<String>(&tileset, "image")?),
+                image: Handle::default(),
+                tile_size: Vec2::new(
+                    json_field(&tileset, "tilewidth")?,
+                    json_field(&tileset, "tileheight")?,
+                ),
+                columns: json_field(&tileset, "columns")?,
+                tile_count: json_field(&tileset, "tilecount")?,
+                spacing: json_optional_field(&tileset, "spacing")?.unwrap_or_default(),
+                margin: json_optional_field(&tileset, "margin")?.unwrap_or_default(),
+            },
+            tile_layers: Vec::new(),
+            objects: Vec::new(),
+            properties: json_properties(&map)?,
+        };
+        tiled_map.check_orientation(&json_field::<String>(&map, "orientation")?)?;
+        tiled_map.read_json_layers(&map)?;
+        tiled_map.validate()?;
+        Ok(tiled_map)
+    }
+
+    fn check_orientation(&self, orientation: &str) -> Result<(), TiledMapError> {
+        if orientation != "isometric" {
+            return Err(TiledMapError::Unsupported(format!(
+                "`{orientation}` maps, only isometric ones are"
+            )));
+        }
+        self.projection().map(|_| ())
+    }
+
+    /// Reads the layers of a `<map>` or `<group>`, flattening groups
+    fn read_xml_layers(&mut self, parent: roxmltree::Node) -> Result<(), TiledMapError> {
+        for node in parent.children().filter(roxmltree::Node::is_element) {
+            match node.tag_name().name() {
+                "layer" => {
+                    let name: String = xml_attribute(node, "name")?;
+                    let properties = xml_properties(node)?;
+                    let Some(data) = node.children().find(|node| node.has_tag_name("data")) else {
+                        return Err(TiledMapError::Invalid(format!(
+                            "layer `{name}` has no data"
+                        )));
+                    };
+                    let encoding = data.attribute("encoding").unwrap_or("xml");
+                    if encoding != "csv" {
+                        return Err(TiledMapError::Unsupported(format!(
+                            "`{encoding}` layer data in layer `{name}`, save the layers as CSV"
+                        )));
+                    }
+                    let gids = data
+                        .text()
+                        .unwrap_or_default()
+                        .split(',')
+                        .map(|gid| gid.trim().parse::<u32>())
+                        .collect::<Result<_, _>>()
+                        .map_err(|error| {
+                            TiledMapError::Invalid(format!("layer `{name}`: {error}"))
+                        })?;
+                    self.tile_layers.push(TiledTileLayer {
+                        layer: tile_layer(&name, &properties)?,
+                        name,
+                        gids,
+                        properties,
+                    });
+                }
+                "objectgroup" => {
+                    for object in node.children().filter(|node| node.has_tag_name("object")) {
+                        let position =
+                            Vec2::new(xml_attribute(object, "x")?, xml_attribute(object, "y")?);
+                        let size = Vec2::new(
+                            xml_optional_attribute(object, "width")?.unwrap_or_default(),
+                            xml_optional_attribute(object, "height")?.unwrap_or_default(),
+                        );
+                        let name = object.attribute("name").unwrap_or_default().to_string();
+                        let properties = xml_properties(object)?;
+                        let shape = if object.children().any(|node| node.has_tag_name("point")) {
+                            TiledShape::Point
+                        } else if object.children().any(|node| node.has_tag_name("ellipse")) {
+                            TiledShape::Ellipse(size)
+                        } else if let Some(polygon) =
+                            object.children().find(|node| node.has_tag_name("polygon"))
+                        {
+                            TiledShape::Polygon(xml_points(polygon)?)
+                        } else {
+                            TiledShape::Rectangle(size)
+                        };
+                        self.objects.push(TiledObject {
+                            collider: self.object_collider(&name, &shape, &properties)?,
+                            name,
+                            kind: object
+                                .attribute("type")
+                                .or_else(|| object.attribute("class"))
+                                .unwrap_or_default()
+                                .to_string(),
+                            position: position + size / 2.0,
+                            properties,
+                        });
+                    }
+                }
+                "group" => self.read_xml_layers(node)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the layers of a map or group, flattening groups
+    fn read_json_layers(&mut self, parent: &Value) -> Result<(), TiledMapError> {
+        for layer in json_field::<Vec<Value>>(parent, "layers")? {
+            match json_field::<String>(&layer, "type")?.as_str() {
+                "tilelayer" => {
+                    let name: String = json_field(&layer, "name")?;
+                    let properties = json_properties(&layer)?;
+                    let Ok(gids) = json_field(&layer, "data") else {
+                        return Err(TiledMapError::Unsupported(format!(
+                            "encoded layer data in layer `{name}`, save the layers as CSV"
+                        )));
+                    };
+                    self.tile_layers.push(TiledTileLayer {
+                        layer: tile_layer(&name, &properties)?,
+                        name,
+                        gids,
+                        properties,
+                    });
+                }
+                "objectgroup" => {
+                    for object in json_field::<Vec<Value>>(&layer, "objects")? {
+                        let position =
+                            Vec2::new(json_field(&object, "x")?, json_field(&object, "y")?);
+                        let size = Vec2::new(
+                            json_optional_field(&object, "width")?.unwrap_or_default(),
+                            json_optional_field(&object, "height")?.unwrap_or_default(),
+                        );
+                        let kind = json_optional_field::<String>(&object, "type")?
+                            .filter(|kind| !kind.is_empty())
+                            .or(json_optional_field(&object, "class")?);
+                        let name: String =
+                            json_optional_field(&object, "name")?.unwrap_or_default();
+                        let properties = json_properties(&object)?;
+                        let shape = if json_optional_field(&object, "point")?.unwrap_or(false) {
+                            TiledShape::Point
+                        } else if json_optional_field(&object, "ellipse")?.unwrap_or(false) {
+                            TiledShape::Ellipse(size)
+                        } else if let Some(points) =
+                            json_optional_field::<Vec<Value>>(&object, "polygon")?
+                        {
+                            TiledShape::Polygon(
+                                points
+                                    .iter()
+                                    .map(|point| {
+                                        Ok(Vec2::new(
+                                            json_field(point, "x")?,
+                                            json_field(point, "y")?,
+                                        ))
+                                    })
+                                    .collect::<Result<_, TiledMapError>>()?,
+                            )
+                        } else {
+                            TiledShape::Rectangle(size)
+                        };
+                        self.objects.push(TiledObject {
+                            collider: self.object_collider(&name, &shape, &properties)?,
+                            name,
+                            kind: kind.unwrap_or_default(),
+                            position: position + size / 2.0,
+                            properties,
+                        });
+                    }
+                }
+                "group" => self.read_json_layers(&layer)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), TiledMapError> {
+        if self.tileset.columns == 0 {
+            return Err(TiledMapError::Invalid(
+                "the tileset has no columns".to_string(),
+            ));
+        }
+        let tile_count = (self.size.x * self.size.y) as usize;
+        let gids = self.tileset.first_gid..self.tileset.first_gid + self.tileset.tile_count as u32;
+        for layer in &self.tile_layers {
+            if layer.gids.len() != tile_count {
+                return Err(TiledMapError::Invalid(format!(
+                    "layer `{}` has {} tiles, the map has {tile_count}",
+                    layer.name,
+                    layer.gids.len()
+                )));
+            }
+            let unknown_gid = layer
+                .gids
+                .iter()
+                .map(|gid| gid & !GID_FLIP_FLAGS)
+                .find(|gid| *gid != 0 && !gids.contains(gid));
+            if let Some(gid) = unknown_gid {
+                return Err(TiledMapError::Invalid(format!(
+                    "layer `{}` uses tile {gid}, which isn't in the tileset",
+                    layer.name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn projection(&self) -> Result<Projection, TiledMapError> {
+        let ratio = self.tile_size.y / self.tile_size.x;
+        [Projection::Dimetric, Projection::Isometric]
+            .into_iter()
+            .find(|projection| (projection.height_ratio() - ratio).abs() < 0.01)
+            .ok_or_else(|| {
+                TiledMapError::Unsupported(format!(
+                    "{}x{} tiles, only 2:1 and true isometric tiles are",
+                    self.tile_size.x, self.tile_size.y
+                ))
+            })
+    }
+
+    /// Returns the grid the map is laid out on
+    #[must_use]
+    pub fn grid(&self) -> IsoGrid {
+        IsoGrid::new(
+            self.projection().unwrap_or(Projection::Dimetric),
+            self.tile_size.x,
+        )
+    }
+
+    /// Returns the tile of the grid at the position of a tile in Tiled
+    ///
+    /// Tiled's tile x goes down-right on screen and its y down-left, while the grid's x goes
+    /// up-right and its y up-left, so the axes are swapped and reversed.
+    fn grid_tile(&self, tiled: Vec2) -> Vec2 {
+        Vec2::new(
+            self.size.y as f32 - 1.0 - tiled.y,
+            self.size.x as f32 - 1.0 - tiled.x,
+        )
+    }
+
+    /// Turns a vector of Tiled's pixel coordinates into world space, like `grid_tile` without
+    /// the offset
+    fn world_vector(&self, tiled: Vec2) -> Vec2 {
+        -Vec2::new(tiled.y, tiled.x) / self.tile_size.y * self.tile_size.x
+    }
+
+    /// Returns the collider of an object, if it's solid
+    fn object_collider(
+        &self,
+        name: &str,
+        shape: &TiledShape,
+        properties: &TiledProperties,
+    ) -> Result<Option<Collider>, TiledMapError> {
+        if properties.get("solid") != Some(&TiledProperty::Bool(true)) {
+            return Ok(None);
+        }
+        let collider = match shape {
+            TiledShape::Point => {
+                return Err(TiledMapError::Invalid(format!(
+                    "object `{name}` is a solid point"
+                )))
+            }
+            TiledShape::Rectangle(size) => Collider::aabb(self.world_vector(*size).abs()),
+            TiledShape::Ellipse(size) if (size.x - size.y).abs() < 0.5 => {
+                Collider::circle(self.world_vector(*size).x.abs() / 2.0)
+            }
+            TiledShape::Ellipse(_) => {
+                return Err(TiledMapError::Unsupported(format!(
+                    "solid ellipses, only circles are (object `{name}`)"
+                )))
+            }
+            TiledShape::Polygon(points) => {
+                let vertices = points.iter().map(|point| self.world_vector(*point));
+                Collider::polygon(vertices.collect()).ok_or_else(|| {
+                    TiledMapError::Unsupported(format!(
+                        "solid concave polygons, only convex ones are (object `{name}`)"
+                    ))
+                })?
+            }
+        };
+        Ok(Some(collider))
+    }
+
+    /// Returns the world position of an object
+    #[must_use]
+    pub fn object_position(&self, object: &TiledObject) -> Vec2 {
+        // Tile positions are on the tile's corner in Tiled and on its center on the grid
+        let tiled = object.position / self.tile_size.y - 0.5;
+        self.grid_tile(tiled) * self.tile_size.x
+    }
+
+    /// Builds the tile map of the map's tile layers, later layers drawing over earlier ones
+    /// mapped to the same `TileLayer`
+    #[must_use]
+    pub fn to_tile_map(&self, atlas: Handle<TextureAtlas>) -> TileMap {
+        let mut tile_map = TileMap::new(
+            UVec2::new(self.size.y, self.size.x),
+            atlas,
+            self.tileset.tile_size,
+        );
+        for layer in &self.tile_layers {
+            for (index, gid) in layer.gids.iter().enumerate() {
+                let Some(atlas_index) = self.tileset.atlas_index(*gid) else {
+                    continue;
+                };
+                let tiled = UVec2::new(index as u32 % self.size.x, index as u32 / self.size.x);
+                let tile = self.grid_tile(tiled.as_vec2()).as_ivec2();
+                tile_map.set(layer.layer, tile, Some(atlas_index));
+            }
+        }
+        tile_map
+    }
+}
+
+/// Error produced when a Tiled map file can't be turned into a `TiledMap`
+#[derive(Debug)]
+pub enum TiledMapError {
+    /// The file isn't valid XML/JSON, or a field is missing or has the wrong type
+    Syntax(String),
+    /// The map uses a Tiled feature the loader doesn't handle
+    Unsupported(String),
+    /// The map is inconsistent, e.g. a layer uses a tile missing from the tileset
+    Invalid(String),
+}
+
+impl fmt::Display for TiledMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Syntax(error) => write!(f, "invalid Tiled map: {error}"),
+            Self::Unsupported(feature) => write!(f, "unsupported Tiled map: {feature}"),
+            Self::Invalid(reason) => write!(f, "invalid Tiled map: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for TiledMapError {}
+
+#[derive(Default)]
+pub struct TiledMapLoader;
+
+impl AssetLoader for TiledMapLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let path = load_context.path();
+            let directory = path.parent().unwrap_or(Path::new(""));
+            let mut tiled_map = if path.extension().is_some_and(|extension| extension == "tmj") {
+                TiledMap::from_tmj(bytes, directory)?
+            } else {
+                TiledMap::from_tmx(bytes, directory)?
+            };
+
+            let image_path = AssetPath::new(tiled_map.tileset.image_path.clone(), None);
+            tiled_map.tileset.image = load_context.get_handle(image_path.clone());
+            load_context.set_default_asset(LoadedAsset::new(tiled_map).with_dependency(image_path));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tmx", "tmj"]
+    }
+}
+
+/// An entity spawned for an object of a `TiledMap`, as a child of the map's entity
+#[derive(Component, Debug, Clone)]
+pub struct MapObject {
+    pub name: String,
+    pub properties: TiledProperties,
+}
+
+/// Where the player starts, from a `player_spawn` object
+#[derive(Component, Debug, Default)]
+pub struct PlayerSpawn;
+
+/// Where an enemy starts, from an `enemy_spawn` object
+#[derive(Component, Debug, Default)]
+pub struct EnemySpawn;
+
+/// From a `bonfire` object
+#[derive(Component, Debug, Default)]
+pub struct Bonfire;
+
+/// From a `door` object
+#[derive(Component, Debug, Default)]
+pub struct Door;
+
+/// Builds the tile map and spawns the objects of every `Handle<TiledMap>` entity once its map
+/// has loaded, and again whenever it's hot-reloaded
+pub fn spawn_tiled_maps_system(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<TiledMap>>,
+    tiled_maps: Res<Assets<TiledMap>>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    mut grid: ResMut<IsoGrid>,
+    map_query: Query<(Entity, &Handle<TiledMap>, Option<&TileMap>)>,
+    object_query: Query<(Entity, &Parent), With<MapObject>>,
+) {
+    for event in events.iter() {
+        let (AssetEvent::Created { handle } | AssetEvent::Modified { handle }) = event else {
+            continue;
+        };
+        let Some(tiled_map) = tiled_maps.get(handle) else {
+            continue;
+        };
+
+        for (entity, map_handle, previous_tile_map) in map_query.iter() {
+            if map_handle != handle {
+                continue;
+            }
+
+            *grid = tiled_map.grid();
+            let atlas = texture_atlases.add(tiled_map.tileset.atlas());
+            let tile_map = tiled_map.to_tile_map(atlas);
+            // Replacing the tile map respawns its chunks, the objects are replaced here
+            if previous_tile_map.is_some() {
+                commands.entity(entity).insert(tile_map);
+            } else {
+                commands.entity(entity).insert(TileMapBundle::new(tile_map));
+            }
+            for (object_entity, parent) in object_query.iter() {
+                if parent.get() == entity {
+                    commands.entity(object_entity).despawn_recursive();
+                }
+            }
+
+            commands.entity(entity).with_children(|parent| {
+                for object in &tiled_map.objects {
+                    let mut object_entity = parent.spawn((
+                        MapObject {
+                            name: object.name.clone(),
+                            properties: object.properties.clone(),
+                        },
+                        WorldPosition(tiled_map.object_position(object)),
+                        SpatialBundle::default(),
+                    ));
+                    if let Some(collider) = &object.collider {
+                        object_entity.insert((collider.clone(), StaticBody));
+                    }
+                    match object.kind.as_str() {
+                        "player_spawn" => object_entity.insert(PlayerSpawn),
+                        "enemy_spawn" => object_entity.insert(EnemySpawn),
+                        "bonfire" => object_entity.insert(Bonfire),
+                        "door" => object_entity.insert(Door),
+                        _ => &mut object_entity,
+                    };
+                }
+            });
+        }
+    }
+}
+
+/// Moves the player to the player spawn of a newly spawned map
+pub fn move_player_to_spawn_system(
+    spawn_query: Query<&WorldPosition, Added<PlayerSpawn>>,
+    mut player_query: Query<&mut WorldPosition, (With<Player>, Without<PlayerSpawn>)>,
+) {
+    let Some(spawn_position) = spawn_query.iter().next() else {
+        return;
+    };
+    for mut world_position in player_query.iter_mut() {
+        *world_position = *spawn_position;
+    }
+}
+
+/// Picks the `TileLayer` of a Tiled tile layer, from its `layer` property or else its name
+fn tile_layer(name: &str, properties: &TiledProperties) -> Result<TileLayer, TiledMapError> {
+    let layer_name = match properties.get("layer") {
+        Some(TiledProperty::String(layer_name)) => layer_name.as_str(),
+        _ => name,
+    };
+    match layer_name.to_lowercase().as_str() {
+        "ground" => Ok(TileLayer::Ground),
+        "decoration" => Ok(TileLayer::Decoration),
+        "walls" => Ok(TileLayer::Walls),
+        _ => Err(TiledMapError::Invalid(format!(
+            "layer `{name}` should be named, or have a `layer` property set to `ground`, \
+             `decoration` or `walls`"
+        ))),
+    }
+}
+
+/// Joins a path relative to the map to the map's directory, resolving `..` so the result is a
+/// plain asset path
+fn resolve_path(directory: &Path, relative: &str) -> PathBuf {
+    let mut resolved = PathBuf::new();
+    for component in directory.join(relative).components() {
+        match component {
+            path::Component::ParentDir => {
+                resolved.pop();
+            }
+            path::Component::CurDir => {}
+            component => resolved.push(component),
+        }
+    }
+    resolved
+}
+
+fn xml_optional_attribute<T: FromStr>(
+    node: roxmltree::Node,
+    name: &str,
+) -> Result<Option<T>, TiledMapError> {
+    node.attribute(name)
+        .map(|value| {
+            value.parse().map_err(|_| {
+                TiledMapError::Syntax(format!(
+                    "invalid `{name}` attribute `{value}` on <{}>",
+                    node.tag_name().name()
+                ))
+            })
+        })
+        .transpose()
+}
+
+fn xml_attribute<T: FromStr>(node: roxmltree::Node, name: &str) -> Result<T, TiledMapError> {
+    xml_optional_attribute(node, name)?.ok_or_else(|| {
+        TiledMapError::Syntax(format!(
+            "missing `{name}` attribute on <{}>",
+            node.tag_name().name()
+        ))
+    })
+}
+
+/// Reads the `<properties>` of an element
+fn xml_properties(node: roxmltree::Node) -> Result<TiledProperties, TiledMapError> {
+    let Some(properties) = node.children().find(|node| node.has_tag_name("properties")) else {
+        return Ok(TiledProperties::new());
+    };
+    properties
+        .children()
+        .filter(|node| node.has_tag_name("property"))
+        .map(|property| {
+            let name: String = xml_attribute(property, "name")?;
+            // Multi-line strings are stored as the element's text
+            let value = property
+                .attribute("value")
+                .or_else(|| property.text())
+                .unwrap_or_default();
+            let property_type = property.attribute("type").unwrap_or("string");
+            Ok((name.clone(), parse_property(&name, property_type, value)?))
+        })
+        .collect()
+}
+
+/// Reads the `points` of a `<polygon>`, written `x,y x,y ...`
+fn xml_points(node: roxmltree::Node) -> Result<Vec<Vec2>, TiledMapError> {
+    let invalid = || TiledMapError::Syntax("invalid polygon points".to_string());
+    xml_attribute::<String>(node, "points")?
+        .split_whitespace()
+        .map(|point| {
+            let (x, y) = point.split_once(',').ok_or_else(invalid)?;
+            Ok(Vec2::new(
+                x.parse().map_err(|_| invalid())?,
+                y.parse().map_err(|_| invalid())?,
+            ))
+        })
+        .collect()
+}
+
+fn parse_property(
+    name: &str,
+    property_type: &str,
+    value: &str,
+) -> Result<TiledProperty, TiledMapError> {
+    let invalid = || TiledMapError::Syntax(format!("invalid {property_type} property `{name}`"));
+    Ok(match property_type {
+        "bool" => TiledProperty::Bool(value.parse().map_err(|_| invalid())?),
+        "int" => TiledProperty::Int(value.parse().map_err(|_| invalid())?),
+        "float" => TiledProperty::Float(value.parse().map_err(|_| invalid())?),
+        _ => TiledProperty::String(value.to_string()),
+    })
+}
+
+fn json_optional_field<T: serde::de::DeserializeOwned>(
+    value: &Value,
+    name: &str,
+) -> Result<Option<T>, TiledMapError> {
+    value
+        .get(name)
+        .map(|field| {
+            T::deserialize(field)
+                .map_err(|error| TiledMapError::Syntax(format!("field `{name}`: {error}")))
+        })
+        .transpose()
+}
+
+fn json_field<T: serde::de::DeserializeOwned>(
+    value: &Value,
+    name: &str,
+) -> Result<T, TiledMapError> {
+    json_optional_field(value, name)?
+        .ok_or_else(|| TiledMapError::Syntax(format!("missing field `{name}`")))
+}
+
+/// Reads the `properties` of a JSON map, layer or object
+fn json_properties(value: &Value) -> Result<TiledProperties, TiledMapError> {
+    let properties: Vec<Value> = json_optional_field(value, "properties")?.unwrap_or_default();
+    properties
+        .iter()
+        .map(|property| {
+            let name: String = json_field(property, "name")?;
+            let property_type: String =
+                json_optional_field(property, "type")?.unwrap_or_else(|| "string".to_string());
+            let value = match &property["value"] {
+                Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            Ok((name.clone(), parse_property(&name, &property_type, &value)?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COURTYARD: &[u8] = include_bytes!("../../assets/maps/courtyard.tmx");
+
+    #[test]
+    fn courtyard_parses() {
+        let map = TiledMap::from_tmx(COURTYARD, Path::new("maps")).unwrap();
+        assert_eq!(map.size, UVec2::new(32, 32));
+        assert_eq!(map.tileset.image_path, Path::new("tiles/terrain.png"));
+        assert_eq!(map.grid().projection, Projection::Dimetric);
+
+        let spawn = map
+            .objects
+            .iter()
+            .find(|object| object.kind == "player_spawn")
+            .unwrap();
+        let grid = map.grid();
+        let position = map.object_position(spawn);
+        assert_eq!(grid.tile_to_world(grid.world_to_tile(position)), position);
+
+        let door = map
+            .objects
+            .iter()
+            .find(|object| object.kind == "door")
+            .unwrap();
+        assert_eq!(door.properties["locked"], TiledProperty::Bool(true));
+        assert_eq!(door.collider, None);
+
+        let bonfire = map
+            .objects
+            .iter()
+            .find(|object| object.kind == "bonfire")
+            .unwrap();
+        assert_eq!(bonfire.collider, Some(Collider::circle(32.0)));
+        let rubble = map
+            .objects
+            .iter()
+            .find(|object| object.name == "rubble")
+            .unwrap();
+        assert!(matches!(rubble.collider, Some(Collider::Polygon { .. })));
+    }
+
+    #[test]
+    fn tiles_keep_their_screen_position() {
+        // A 2x1 map, Tiled's tile (1, 0) being to the right of and below (0, 0) on screen
+        let map = TiledMap::from_tmj(
+            br#"{
+                "orientation": "isometric", "width": 2, "height": 1,
+                "tilewidth": 64, "tileheight": 32,
+                "tilesets": [{
+                    "firstgid": 1, "image": "terrain.png", "tilewidth": 64, "tileheight": 64,
+                    "columns": 4, "tilecount": 4
+                }],
+                "layers": [{ "type": "tilelayer", "name": "walls", "data": [4, 0] }]
+            }"#,
+            Path::new(""),
+        )
+        .unwrap();
+        let tile_map = map.to_tile_map(Handle::default());
+        let grid = map.grid();
+
+        let wall = IVec2::new(0, 1);
+        assert_eq!(tile_map.get(TileLayer::Walls, wall), Some(3));
+        assert_eq!(tile_map.get(TileLayer::Walls, IVec2::ZERO), None);
+        let offset = grid.tile_to_screen(IVec2::ZERO) - grid.tile_to_screen(wall);
+        assert_eq!(offset, Vec2::new(32.0, -16.0));
+    }
+
+    #[test]
+    fn tilesets_without_columns_are_rejected() {
+        let tmj = br#"{
+            "orientation": "isometric", "width": 1, "height": 1,
+            "tilewidth": 64, "tileheight": 32,
+            "tilesets": [{
+                "firstgid": 1, "image": "terrain.png", "tilewidth": 64, "tileheight": 64,
+                "columns": 0, "tilecount": 4
+            }],
+            "layers": [{ "type": "tilelayer", "name": "walls", "data": [1] }]
+        }"#;
+        assert!(matches!(
+            TiledMap::from_tmj(tmj, Path::new("")),
+            Err(TiledMapError::Invalid(_))
+        ));
+
+        let tmx =
+            br#"<map orientation="isometric" width="1" height="1" tilewidth="64" tileheight="32">
+            <tileset firstgid="1" tilewidth="64" tileheight="64" columns="0" tilecount="4">
+                <image source="terrain.png"/>
+            </tileset>
+            <layer name="walls" width="1" height="1"><data encoding="csv">1</data></layer>
+        </map>"#;
+        assert!(matches!(
+            TiledMap::from_tmx(tmx, Path::new("")),
+            Err(TiledMapError::Invalid(_))
+        ));
+    }
+}
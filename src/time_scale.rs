@@ -0,0 +1,93 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use std::time::Duration;
+
+pub struct TimeScalePlugin;
+
+impl Plugin for TimeScalePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TimeScale>()
+            .add_system(tick_hitstop_system.in_base_set(CoreSet::PreUpdate));
+    }
+}
+
+/// Global time dilation of the game, read through `ScaledTime` instead of `Time::delta`
+#[derive(Resource)]
+pub struct TimeScale {
+    /// Multiplier of the game's time, e.g. 0.25 for slow-motion on a parry
+    pub scale: f32,
+    /// Real time left before the current hitstop ends
+    hitstop_remaining: Duration,
+}
+
+impl Default for TimeScale {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            hitstop_remaining: Duration::ZERO,
+        }
+    }
+}
+
+impl TimeScale {
+    /// Freeze the game for `duration` of real time, e.g. on impact
+    ///
+    /// Hitstops requested while one is running don't add up, the freeze ends with the longest
+    /// one so a flurry of hits doesn't stall the game.
+    pub fn request_hitstop(&mut self, duration: Duration) {
+        self.hitstop_remaining = self.hitstop_remaining.max(duration);
+    }
+
+    pub fn request_hitstop_millis(&mut self, millis: u64) {
+        self.request_hitstop(Duration::from_millis(millis));
+    }
+
+    #[must_use]
+    pub fn is_hitstopped(&self) -> bool {
+        !self.hitstop_remaining.is_zero()
+    }
+
+    /// Returns the time multiplier of an entity, given its `TimeScaleOverride` if it has one
+    ///
+    /// Hitstops freeze every entity, overrides included.
+    #[must_use]
+    pub fn scale_for(&self, time_scale_override: Option<&TimeScaleOverride>) -> f32 {
+        if self.is_hitstopped() {
+            0.0
+        } else {
+            time_scale_override.map_or(self.scale, |time_scale_override| time_scale_override.0)
+        }
+    }
+}
+
+/// Time multiplier of an entity, used instead of `TimeScale::scale`
+#[derive(Component, Debug, Clone, Copy)]
+pub struct TimeScaleOverride(pub f32);
+
+/// `Time` dilated by the `TimeScale`
+#[derive(SystemParam)]
+pub struct ScaledTime<'w> {
+    time: Res<'w, Time>,
+    time_scale: Res<'w, TimeScale>,
+}
+
+impl<'w> ScaledTime<'w> {
+    /// Returns the scaled time elapsed since the last frame for an entity, given its
+    /// `TimeScaleOverride` if it has one
+    #[must_use]
+    pub fn delta(&self, time_scale_override: Option<&TimeScaleOverride>) -> Duration {
+        self.time
+            .delta()
+            .mul_f32(self.time_scale.scale_for(time_scale_override).max(0.0))
+    }
+
+    #[must_use]
+    pub fn delta_seconds(&self, time_scale_override: Option<&TimeScaleOverride>) -> f32 {
+        self.delta(time_scale_override).as_secs_f32()
+    }
+}
+
+/// Counts the hitstop down in real time, so it isn't frozen by itself
+fn tick_hitstop_system(time: Res<Time>, mut time_scale: ResMut<TimeScale>) {
+    time_scale.hitstop_remaining = time_scale.hitstop_remaining.saturating_sub(time.delta());
+}
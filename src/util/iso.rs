@@ -0,0 +1,196 @@
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+
+pub struct IsoPlugin;
+
+impl Plugin for IsoPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<IsoGrid>().add_system(
+            sync_world_positions_system
+                .in_base_set(CoreSet::PostUpdate)
+                .before(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+/// How the ground plane is projected on the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Projection {
+    /// Tiles twice as wide as they are tall, the usual pixel art projection
+    Dimetric,
+    /// True isometric projection, the tile edges at 30 degrees from the horizontal
+    Isometric,
+}
+
+impl Projection {
+    /// Height of a tile on screen relative to its width
+    #[must_use]
+    pub fn height_ratio(self) -> f32 {
+        match self {
+            Projection::Dimetric => 0.5,
+            Projection::Isometric => 30f32.to_radians().tan(),
+        }
+    }
+}
+
+/// The isometric grid of the world, converting between its three spaces:
+///
+/// - Tile space: integer coordinates of the tiles, whose centers are on the integers
+/// - World space: the ground plane along the tile axes, where a tile is `tile_width` units wide.
+///   Gameplay (movement, collisions, distances) happens here.
+/// - Screen space: where the sprites are drawn. World x goes up-right, world y goes up-left.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct IsoGrid {
+    pub projection: Projection,
+    /// Width of a tile on screen, which is also its size in world units
+    pub tile_width: f32,
+}
+
+impl Default for IsoGrid {
+    fn default() -> Self {
+        Self {
+            projection: Projection::Dimetric,
+            tile_width: 64.0,
+        }
+    }
+}
+
+impl IsoGrid {
+    #[must_use]
+    pub fn new(projection: Projection, tile_width: f32) -> Self {
+        Self {
+            projection,
+            tile_width,
+        }
+    }
+
+    /// Size of a tile on screen
+    #[must_use]
+    pub fn tile_size(&self) -> Vec2 {
+        Vec2::new(
+            self.tile_width,
+            self.tile_width * self.projection.height_ratio(),
+        )
+    }
+
+    /// Returns the world position of the center of a tile
+    #[must_use]
+    pub fn tile_to_world(&self, tile: IVec2) -> Vec2 {
+        tile.as_vec2() * self.tile_width
+    }
+
+    /// Returns the tile containing a world position
+    #[must_use]
+    pub fn world_to_tile(&self, world: Vec2) -> IVec2 {
+        (world / self.tile_width).round().as_ivec2()
+    }
+
+    /// The projection is linear, so this also turns world-space directions into screen-space ones
+    #[must_use]
+    pub fn world_to_screen(&self, world: Vec2) -> Vec2 {
+        let tile = world / self.tile_width;
+        let half_size = self.tile_size() / 2.0;
+        Vec2::new(
+            (tile.x - tile.y) * half_size.x,
+            (tile.x + tile.y) * half_size.y,
+        )
+    }
+
+    #[must_use]
+    pub fn screen_to_world(&self, screen: Vec2) -> Vec2 {
+        let half_size = self.tile_size() / 2.0;
+        let difference = screen.x / half_size.x;
+        let sum = screen.y / half_size.y;
+        Vec2::new(sum + difference, sum - difference) / 2.0 * self.tile_width
+    }
+
+    /// Returns the screen position of the center of a tile
+    #[must_use]
+    pub fn tile_to_screen(&self, tile: IVec2) -> Vec2 {
+        self.world_to_screen(self.tile_to_world(tile))
+    }
+
+    /// Returns the tile drawn at a screen position
+    #[must_use]
+    pub fn screen_to_tile(&self, screen: Vec2) -> IVec2 {
+        self.world_to_tile(self.screen_to_world(screen))
+    }
+}
+
+/// Position of an entity on the ground plane, in world space
+///
+/// The entity's `Transform` follows it on screen, keeping its z.
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq)]
+pub struct WorldPosition(pub Vec2);
+
+/// Places every entity with a `WorldPosition` on screen
+pub fn sync_world_positions_system(
+    grid: Res<IsoGrid>,
+    mut query: Query<(&WorldPosition, &mut Transform), Changed<WorldPosition>>,
+) {
+    for (world_position, mut transform) in query.iter_mut() {
+        let screen = grid.world_to_screen(world_position.0);
+        transform.translation.x = screen.x;
+        transform.translation.y = screen.y;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    #[test]
+    fn tile_axes_project_on_the_diamond_edges() {
+        let grid = IsoGrid::new(Projection::Dimetric, 64.0);
+        assert_eq!(grid.tile_to_screen(IVec2::new(1, 0)), Vec2::new(32.0, 16.0));
+        assert_eq!(
+            grid.tile_to_screen(IVec2::new(0, 1)),
+            Vec2::new(-32.0, 16.0)
+        );
+
+        let grid = IsoGrid::new(Projection::Isometric, 64.0);
+        let screen = grid.tile_to_screen(IVec2::new(1, 0));
+        assert!((screen.y / screen.x - 30f32.to_radians().tan()).abs() < EPSILON);
+    }
+
+    #[test]
+    fn screen_to_world_inverts_world_to_screen() {
+        for projection in [Projection::Dimetric, Projection::Isometric] {
+            let grid = IsoGrid::new(projection, 48.0);
+            for world in [
+                Vec2::ZERO,
+                Vec2::new(100.0, -35.5),
+                Vec2::new(-12.25, 700.0),
+            ] {
+                let round_trip = grid.screen_to_world(grid.world_to_screen(world));
+                assert!(
+                    round_trip.abs_diff_eq(world, EPSILON),
+                    "{projection:?}, {world}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn positions_within_a_tile_map_to_it() {
+        let grid = IsoGrid::default();
+        let tile = IVec2::new(3, -2);
+        let center = grid.tile_to_world(tile);
+        let half = grid.tile_width / 2.0 - EPSILON;
+        for offset in [
+            Vec2::ZERO,
+            Vec2::new(half, half),
+            Vec2::new(-half, half),
+            Vec2::new(half, -half),
+            Vec2::new(-half, -half),
+        ] {
+            assert_eq!(grid.world_to_tile(center + offset), tile);
+            assert_eq!(
+                grid.screen_to_tile(grid.world_to_screen(center + offset)),
+                tile
+            );
+        }
+    }
+}
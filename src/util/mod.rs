@@ -0,0 +1,357 @@
+pub mod iso;
+
+use bevy::prelude::*;
+use std::f32::consts::{PI, TAU};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    North,
+    NorthEast,
+    East,
+    SouthEast,
+    South,
+    SouthWest,
+    West,
+    NorthWest,
+}
+
+impl Direction {
+    /// Returns the direction mirrored horizontally, e.g. West for East
+    #[must_use]
+    pub fn mirrored(self) -> Self {
+        match self {
+            Direction::NorthEast => Direction::NorthWest,
+            Direction::East => Direction::West,
+            Direction::SouthEast => Direction::SouthWest,
+            Direction::SouthWest => Direction::SouthEast,
+            Direction::West => Direction::East,
+            Direction::NorthWest => Direction::NorthEast,
+            Direction::North | Direction::South => self,
+        }
+    }
+}
+
+/// How many sectors a `DirectionQuantizer` splits the circle into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Directions {
+    Four,
+    Eight,
+    Sixteen,
+}
+
+impl Directions {
+    #[must_use]
+    pub fn count(self) -> usize {
+        match self {
+            Directions::Four => 4,
+            Directions::Eight => 8,
+            Directions::Sixteen => 16,
+        }
+    }
+}
+
+/// Turns vectors into one of 4, 8 or 16 directions based on their angle
+///
+/// Sectors are numbered counter-clockwise starting from East, each centered on its direction.
+/// The quantizer remembers its last sector, so with some hysteresis a vector hovering around a
+/// boundary doesn't flicker between two directions.
+#[derive(Debug, Clone)]
+pub struct DirectionQuantizer {
+    pub directions: Directions,
+    /// Counter-clockwise rotation of the sectors, in radians
+    pub angle_offset: f32,
+    /// Scale applied to the vector's y before measuring its angle
+    ///
+    /// 2 for a 2:1 dimetric projection, so the screen-space diagonals of the tiles fall on the
+    /// diagonal sectors.
+    pub vertical_scale: f32,
+    /// How far past a boundary, in radians, a vector must go to leave the current sector
+    pub hysteresis: f32,
+    sector: Option<usize>,
+}
+
+impl DirectionQuantizer {
+    #[must_use]
+    pub fn new(directions: Directions) -> Self {
+        Self {
+            directions,
+            angle_offset: 0.0,
+            vertical_scale: 1.0,
+            hysteresis: 0.0,
+            sector: None,
+        }
+    }
+
+    /// A quantizer for a 2:1 dimetric projection
+    #[must_use]
+    pub fn isometric(directions: Directions) -> Self {
+        Self::new(directions).with_vertical_scale(2.0)
+    }
+
+    #[must_use]
+    pub fn with_angle_offset(mut self, angle_offset: f32) -> Self {
+        self.angle_offset = angle_offset;
+        self
+    }
+
+    #[must_use]
+    pub fn with_vertical_scale(mut self, vertical_scale: f32) -> Self {
+        self.vertical_scale = vertical_scale;
+        self
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `hysteresis` is negative or not less than half a sector, which would make
+    /// some sectors impossible to leave
+    #[must_use]
+    pub fn with_hysteresis(mut self, hysteresis: f32) -> Self {
+        assert!(
+            (0.0..self.sector_width() / 2.0).contains(&hysteresis),
+            "hysteresis {hysteresis} out of range"
+        );
+        self.hysteresis = hysteresis;
+        self
+    }
+
+    /// Angle covered by each sector, in radians
+    #[must_use]
+    pub fn sector_width(&self) -> f32 {
+        TAU / self.directions.count() as f32
+    }
+
+    /// Returns the current sector, or `None` if no vector was quantized yet
+    #[must_use]
+    pub fn sector(&self) -> Option<usize> {
+        self.sector
+    }
+
+    /// Returns the direction of the current sector, or `None` if no vector was quantized yet
+    ///
+    /// With 16 directions, the odd sectors in between two directions map to the one clockwise
+    /// of them.
+    #[must_use]
+    pub fn direction(&self) -> Option<Direction> {
+        let sector = self.sector?;
+        let eighth = match self.directions {
+            Directions::Four => sector * 2,
+            Directions::Eight => sector,
+            Directions::Sixteen => sector / 2,
+        };
+        Some(match eighth {
+            0 => Direction::East,
+            1 => Direction::NorthEast,
+            2 => Direction::North,
+            3 => Direction::NorthWest,
+            4 => Direction::West,
+            5 => Direction::SouthWest,
+            6 => Direction::South,
+            _ => Direction::SouthEast,
+        })
+    }
+
+    /// Quantize `vector`, returning the new sector
+    ///
+    /// A zero vector keeps the current sector.
+    pub fn quantize(&mut self, vector: Vec2) -> Option<usize> {
+        let vector = Vec2::new(vector.x, vector.y * self.vertical_scale);
+        if vector == Vec2::ZERO || !vector.is_finite() {
+            return self.sector;
+        }
+
+        let width = self.sector_width();
+        let angle = (vector.y.atan2(vector.x) - self.angle_offset).rem_euclid(TAU);
+
+        if let Some(sector) = self.sector {
+            // Angle from the center of the current sector, in [-PI, PI)
+            let from_center = (angle - sector as f32 * width + PI).rem_euclid(TAU) - PI;
+            if from_center.abs() <= width / 2.0 + self.hysteresis {
+                return self.sector;
+            }
+        }
+
+        let sector = ((angle + width / 2.0) / width).floor() as usize % self.directions.count();
+        self.sector = Some(sector);
+        self.sector
+    }
+
+    /// Quantize `vector`, returning the direction of the new sector
+    pub fn quantize_direction(&mut self, vector: Vec2) -> Option<Direction> {
+        self.quantize(vector);
+        self.direction()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Small enough to stay on one side of a boundary, large enough to survive rounding
+    const EPSILON: f32 = 1e-3;
+
+    fn at_angle(angle: f32) -> Vec2 {
+        Vec2::new(angle.cos(), angle.sin())
+    }
+
+    fn quantize_once(quantizer: &DirectionQuantizer, vector: Vec2) -> Option<usize> {
+        quantizer.clone().quantize(vector)
+    }
+
+    #[test]
+    fn every_boundary_separates_neighbouring_sectors() {
+        for directions in [Directions::Four, Directions::Eight, Directions::Sixteen] {
+            let quantizer = DirectionQuantizer::new(directions);
+            let count = directions.count();
+            let width = quantizer.sector_width();
+            for sector in 0..count {
+                // Boundary between `sector` and the next one, counter-clockwise
+                let boundary = (sector as f32 + 0.5) * width;
+                assert_eq!(
+                    quantize_once(&quantizer, at_angle(boundary - EPSILON)),
+                    Some(sector),
+                    "{directions:?}, before boundary {sector}"
+                );
+                assert_eq!(
+                    quantize_once(&quantizer, at_angle(boundary + EPSILON)),
+                    Some((sector + 1) % count),
+                    "{directions:?}, after boundary {sector}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn sector_centers_map_to_their_direction() {
+        let mut quantizer = DirectionQuantizer::new(Directions::Eight);
+        let expected = [
+            (Vec2::new(1.0, 0.0), Direction::East),
+            (Vec2::new(1.0, 1.0), Direction::NorthEast),
+            (Vec2::new(0.0, 1.0), Direction::North),
+            (Vec2::new(-1.0, 1.0), Direction::NorthWest),
+            (Vec2::new(-1.0, 0.0), Direction::West),
+            (Vec2::new(-1.0, -1.0), Direction::SouthWest),
+            (Vec2::new(0.0, -1.0), Direction::South),
+            (Vec2::new(1.0, -1.0), Direction::SouthEast),
+        ];
+        for (vector, direction) in expected {
+            assert_eq!(quantizer.quantize_direction(vector), Some(direction));
+        }
+    }
+
+    #[test]
+    fn exact_diagonals_are_not_gaps() {
+        let diagonal = std::f32::consts::FRAC_1_SQRT_2;
+        let mut quantizer = DirectionQuantizer::new(Directions::Eight);
+        assert_eq!(
+            quantizer.quantize_direction(Vec2::new(diagonal, diagonal)),
+            Some(Direction::NorthEast)
+        );
+        assert_eq!(
+            quantizer.quantize_direction(Vec2::new(-diagonal, -diagonal)),
+            Some(Direction::SouthWest)
+        );
+    }
+
+    #[test]
+    fn four_and_sixteen_directions_map_to_directions() {
+        let mut quantizer = DirectionQuantizer::new(Directions::Four);
+        assert_eq!(
+            quantizer.quantize_direction(Vec2::new(0.0, -1.0)),
+            Some(Direction::South)
+        );
+
+        let mut quantizer = DirectionQuantizer::new(Directions::Sixteen);
+        assert_eq!(quantizer.quantize(at_angle(PI / 8.0)), Some(1));
+        assert_eq!(quantizer.direction(), Some(Direction::East));
+        assert_eq!(quantizer.quantize(at_angle(PI / 4.0)), Some(2));
+        assert_eq!(quantizer.direction(), Some(Direction::NorthEast));
+    }
+
+    #[test]
+    fn zero_vector_keeps_the_current_sector() {
+        let mut quantizer = DirectionQuantizer::new(Directions::Eight);
+        assert_eq!(quantizer.quantize(Vec2::ZERO), None);
+        assert_eq!(quantizer.quantize(Vec2::new(0.0, 1.0)), Some(2));
+        assert_eq!(quantizer.quantize(Vec2::ZERO), Some(2));
+    }
+
+    #[test]
+    fn hysteresis_holds_the_sector_past_every_boundary() {
+        let hysteresis = 0.1;
+        for directions in [Directions::Four, Directions::Eight, Directions::Sixteen] {
+            let quantizer = DirectionQuantizer::new(directions).with_hysteresis(hysteresis);
+            let count = directions.count();
+            let width = quantizer.sector_width();
+            for sector in 0..count {
+                let center = sector as f32 * width;
+                let next = (sector + 1) % count;
+                let previous = (sector + count - 1) % count;
+
+                let mut quantizer = quantizer.clone();
+                quantizer.quantize(at_angle(center));
+                // Within the margin past either boundary, the sector is kept
+                assert_eq!(
+                    quantizer.quantize(at_angle(center + width / 2.0 + hysteresis - EPSILON)),
+                    Some(sector)
+                );
+                assert_eq!(
+                    quantizer.quantize(at_angle(center - width / 2.0 - hysteresis + EPSILON)),
+                    Some(sector)
+                );
+                // Beyond it, the neighbouring sector is taken
+                assert_eq!(
+                    quantizer.quantize(at_angle(center + width / 2.0 + hysteresis + EPSILON)),
+                    Some(next)
+                );
+                quantizer.quantize(at_angle(center));
+                assert_eq!(
+                    quantizer.quantize(at_angle(center - width / 2.0 - hysteresis - EPSILON)),
+                    Some(previous)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn angle_offset_rotates_the_boundaries() {
+        let quantizer = DirectionQuantizer::new(Directions::Four).with_angle_offset(PI / 4.0);
+        // The East sector now spans from 0 to 90 degrees
+        assert_eq!(quantize_once(&quantizer, at_angle(EPSILON)), Some(0));
+        assert_eq!(quantize_once(&quantizer, at_angle(-EPSILON)), Some(3));
+        assert_eq!(
+            quantize_once(&quantizer, at_angle(PI / 2.0 - EPSILON)),
+            Some(0)
+        );
+        assert_eq!(
+            quantize_once(&quantizer, at_angle(PI / 2.0 + EPSILON)),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn isometric_diagonals_fall_on_diagonal_sectors() {
+        let quantizer = DirectionQuantizer::isometric(Directions::Eight);
+        let expected = [
+            (Vec2::new(2.0, 1.0), Direction::NorthEast),
+            (Vec2::new(-2.0, 1.0), Direction::NorthWest),
+            (Vec2::new(-2.0, -1.0), Direction::SouthWest),
+            (Vec2::new(2.0, -1.0), Direction::SouthEast),
+        ];
+        for (vector, direction) in expected {
+            assert_eq!(
+                quantizer.clone().quantize_direction(vector),
+                Some(direction)
+            );
+        }
+        // The boundary between East and NorthEast is squashed vertically too
+        let boundary = (0.5 * (PI / 8.0).tan()).atan();
+        assert_eq!(
+            quantize_once(&quantizer, at_angle(boundary - EPSILON)),
+            Some(0)
+        );
+        assert_eq!(
+            quantize_once(&quantizer, at_angle(boundary + EPSILON)),
+            Some(1)
+        );
+    }
+}
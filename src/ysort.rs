@@ -0,0 +1,139 @@
+use crate::util::iso::{sync_world_positions_system, IsoGrid};
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+
+pub struct YSortPlugin;
+
+impl Plugin for YSortPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            y_sort_system
+                .in_base_set(CoreSet::PostUpdate)
+                .after(sync_world_positions_system)
+                .before(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+/// Z around which the y-sorted sprites are spread, above the ground layers of the tile maps
+pub const Y_SORT_BASE_Z: f32 = -400.0;
+/// Z difference between sprites one screen pixel apart, so sprites stay in front of the ground
+/// and behind the camera up to millions of pixels from the origin
+const Z_PER_PIXEL: f32 = 1e-4;
+
+/// Derives the z of the entity's `Transform` from its screen y, so that sprites lower on screen,
+/// closer to the viewer, are drawn in front
+///
+/// The translation is taken as the screen position, so the entity's parents must not offset it
+/// vertically.
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub struct YSort {
+    /// Screen y offset from the translation to the point the sprite is sorted by, usually where
+    /// it touches the ground
+    pub pivot: f32,
+    /// Added to the z after sorting, e.g. to keep a weapon in front of the character holding it
+    pub offset: f32,
+}
+
+impl YSort {
+    #[must_use]
+    pub fn with_pivot(pivot: f32) -> Self {
+        Self { pivot, offset: 0.0 }
+    }
+
+    /// Sorts a tall object standing on `footprint` tiles, its translation at the center of the
+    /// footprint, by its front-most tile
+    ///
+    /// Characters are drawn behind it once they are past its front tile. Past its side tiles,
+    /// they may still be drawn in front of it, so long walls are best split into tiles like tile
+    /// maps do.
+    #[must_use]
+    pub fn footprint(grid: &IsoGrid, footprint: UVec2) -> Self {
+        let front_tile = (footprint.as_vec2() - 1.0) / 2.0 * -grid.tile_width;
+        Self::with_pivot(grid.world_to_screen(front_tile).y)
+    }
+
+    #[must_use]
+    pub fn with_offset(mut self, offset: f32) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Returns the z of a sprite at screen y `y`
+    #[must_use]
+    pub fn z(&self, y: f32) -> f32 {
+        Y_SORT_BASE_Z - (y + self.pivot) * Z_PER_PIXEL + self.offset
+    }
+}
+
+/// Sets the z of every y-sorted entity that moved
+pub fn y_sort_system(mut query: Query<(&YSort, &mut Transform), Changed<Transform>>) {
+    for (y_sort, mut transform) in query.iter_mut() {
+        let z = y_sort.z(transform.translation.y);
+        // Only write actual changes, so the entity isn't seen as moved again next frame
+        if transform.translation.z != z {
+            transform.translation.z = z;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::iso::Projection;
+
+    #[test]
+    fn lower_sprites_are_drawn_in_front() {
+        let y_sort = YSort::default();
+        assert!(y_sort.z(-10.0) > y_sort.z(10.0));
+        assert!(y_sort.z(10.0) > Y_SORT_BASE_Z - 1.0 && y_sort.z(10.0) < Y_SORT_BASE_Z);
+
+        // Sorted as if 20 pixels lower
+        assert_eq!(YSort::with_pivot(-20.0).z(20.0), y_sort.z(0.0));
+        let z = YSort::with_pivot(-20.0).with_offset(0.5).z(20.0);
+        assert!((z - (y_sort.z(0.0) + 0.5)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn characters_walk_behind_objects_past_their_front_tile() {
+        let grid = IsoGrid::new(Projection::Dimetric, 64.0);
+        let mut app = App::new();
+        app.add_system(y_sort_system);
+
+        // A 3x3 pillar centered on tile (5, 5), its front tile being (4, 4)
+        let spawn = |app: &mut App, tile: IVec2, y_sort: YSort| {
+            let screen = grid.tile_to_screen(tile);
+            app.world
+                .spawn((y_sort, Transform::from_translation(screen.extend(0.0))))
+                .id()
+        };
+        let pillar = spawn(
+            &mut app,
+            IVec2::new(5, 5),
+            YSort::footprint(&grid, UVec2::new(3, 3)),
+        );
+        let character = spawn(&mut app, IVec2::new(3, 3), YSort::default());
+
+        let is_behind = |app: &mut App, tile: IVec2| {
+            app.world
+                .get_mut::<Transform>(character)
+                .unwrap()
+                .translation = grid.tile_to_screen(tile).extend(0.0);
+            app.update();
+            let z = |entity| app.world.get::<Transform>(entity).unwrap().translation.z;
+            z(character) < z(pillar)
+        };
+        // In front of the pillar, then on its front tile's side and back tiles
+        assert!(!is_behind(&mut app, IVec2::new(3, 3)));
+        assert!(!is_behind(&mut app, IVec2::new(3, 4)));
+        assert!(is_behind(&mut app, IVec2::new(4, 5)));
+        assert!(is_behind(&mut app, IVec2::new(6, 6)));
+        // Walking back out
+        assert!(!is_behind(&mut app, IVec2::new(5, 2)));
+
+        // Sorted by its center instead, the pillar would be drawn behind a character standing on
+        // its side tile
+        let z = |tile| YSort::default().z(grid.tile_to_screen(tile).y);
+        assert!(z(IVec2::new(4, 5)) > z(IVec2::new(5, 5)));
+    }
+}